@@ -0,0 +1,462 @@
+//! Whitelist, bans and operators, stored in the same JSON files as vanilla
+
+use std::fs;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use async_std::sync::{Arc, RwLock};
+use log::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::error::{McError, McResult};
+
+const WHITELIST_FILE: &str = "whitelist.json";
+const BANNED_PLAYERS_FILE: &str = "banned-players.json";
+const BANNED_IPS_FILE: &str = "banned-ips.json";
+const OPS_FILE: &str = "ops.json";
+
+/// Format of ban creation and expiry dates, e.g. `2020-10-17 12:00:00 +0000`
+const DATE_FORMAT: &str = "%F %T %z";
+const NEVER_EXPIRES: &str = "forever";
+
+pub const DEFAULT_BAN_REASON: &str = "Banned by an operator.";
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WhitelistEntry {
+    pub uuid: Uuid,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpEntry {
+    pub uuid: Uuid,
+    pub name: String,
+    /// Permission level, 1 to 4
+    pub level: u8,
+    /// Unused, kept for vanilla
+    #[serde(default)]
+    pub bypasses_player_limit: bool,
+}
+
+/// Details common to player and IP bans
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Ban {
+    pub created: String,
+    /// Who issued the ban
+    pub source: String,
+    /// Date or "forever"
+    pub expires: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PlayerBan {
+    pub uuid: Uuid,
+    pub name: String,
+    #[serde(flatten)]
+    pub ban: Ban,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct IpBan {
+    pub ip: IpAddr,
+    #[serde(flatten)]
+    pub ban: Ban,
+}
+
+/// Shared between the game, which changes them through commands, and logins, which check them.
+/// Operators are only used by the game
+#[derive(Clone)]
+pub struct AccessLists(Arc<RwLock<Lists>>);
+
+struct Lists {
+    dir: PathBuf,
+    whitelist_enabled: bool,
+    whitelist: Vec<WhitelistEntry>,
+    banned_players: Vec<PlayerBan>,
+    banned_ips: Vec<IpBan>,
+    ops: Vec<OpEntry>,
+}
+
+impl Ban {
+    /// Permanent ban created now
+    pub fn new(source: &str, reason: Option<&str>) -> Self {
+        Self {
+            created: format_date(OffsetDateTime::now_utc()),
+            source: source.to_owned(),
+            expires: NEVER_EXPIRES.to_owned(),
+            reason: reason.unwrap_or(DEFAULT_BAN_REASON).to_owned(),
+        }
+    }
+
+    /// Unparseable dates never expire
+    fn is_expired(&self, now: OffsetDateTime) -> bool {
+        if self.expires == NEVER_EXPIRES {
+            return false;
+        }
+
+        match OffsetDateTime::parse(&self.expires, DATE_FORMAT) {
+            Ok(expiry) => expiry <= now,
+            Err(e) => {
+                warn!("invalid ban expiry date '{}': {}", self.expires, e);
+                false
+            }
+        }
+    }
+
+    /// Disconnect message, starting with what's banned
+    fn message(&self, banned: &str) -> String {
+        let mut message = format!("{}\nReason: {}", banned, self.reason);
+        if self.expires != NEVER_EXPIRES {
+            message.push_str(&format!("\nYour ban will be removed on {}", self.expires));
+        }
+        message
+    }
+}
+
+impl AccessLists {
+    /// Loads the lists from the given directory, treating missing files as empty
+    pub fn load(dir: impl Into<PathBuf>, whitelist_enabled: bool) -> McResult<Self> {
+        let lists = Lists::load(dir.into(), whitelist_enabled)?;
+        Ok(Self(Arc::new(RwLock::new(lists))))
+    }
+
+    /// Rereads the lists from disk
+    pub async fn reload(&self) -> McResult<()> {
+        let mut lists = self.0.write().await;
+        *lists = Lists::load(lists.dir.clone(), lists.whitelist_enabled)?;
+        Ok(())
+    }
+
+    /// Fails with the disconnect message if the player may not join
+    pub async fn check_login(&self, uuid: &Uuid, name: &str, ip: IpAddr) -> McResult<()> {
+        let lists = self.0.read().await;
+        let now = OffsetDateTime::now_utc();
+
+        if let Some(ban) = lists
+            .banned_players
+            .iter()
+            .find(|b| b.uuid == *uuid && !b.ban.is_expired(now))
+        {
+            info!("refusing banned player {} ({})", name, uuid);
+            return Err(McError::LoginDenied(
+                ban.ban.message("You are banned from this server."),
+            ));
+        }
+
+        if let Some(ban) = lists
+            .banned_ips
+            .iter()
+            .find(|b| b.ip == ip && !b.ban.is_expired(now))
+        {
+            info!("refusing {} from banned IP {}", name, ip);
+            return Err(McError::LoginDenied(
+                ban.ban
+                    .message("Your IP address is banned from this server."),
+            ));
+        }
+
+        if lists.whitelist_enabled && !lists.whitelist.iter().any(|e| e.uuid == *uuid) {
+            info!("refusing {} who isn't whitelisted", name);
+            return Err(McError::LoginDenied(
+                "You are not white-listed on this server!".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub async fn whitelist_enabled(&self) -> bool {
+        self.0.read().await.whitelist_enabled
+    }
+
+    /// Not persisted, the config decides whether it's enabled at startup
+    pub async fn set_whitelist_enabled(&self, enabled: bool) {
+        self.0.write().await.whitelist_enabled = enabled;
+    }
+
+    pub async fn whitelist(&self) -> Vec<WhitelistEntry> {
+        self.0.read().await.whitelist.clone()
+    }
+
+    /// False if already whitelisted
+    pub async fn whitelist_add(&self, entry: WhitelistEntry) -> McResult<bool> {
+        let mut lists = self.0.write().await;
+        if lists.whitelist.iter().any(|e| e.uuid == entry.uuid) {
+            return Ok(false);
+        }
+
+        lists.whitelist.push(entry);
+        save_list(&lists.dir.join(WHITELIST_FILE), &lists.whitelist)?;
+        Ok(true)
+    }
+
+    /// False if not whitelisted
+    pub async fn whitelist_remove(&self, name: &str) -> McResult<bool> {
+        let mut lists = self.0.write().await;
+        let count = lists.whitelist.len();
+        lists
+            .whitelist
+            .retain(|e| !e.name.eq_ignore_ascii_case(name));
+        if lists.whitelist.len() == count {
+            return Ok(false);
+        }
+
+        save_list(&lists.dir.join(WHITELIST_FILE), &lists.whitelist)?;
+        Ok(true)
+    }
+
+    pub async fn banned_players(&self) -> Vec<PlayerBan> {
+        self.0.read().await.banned_players.clone()
+    }
+
+    pub async fn banned_ips(&self) -> Vec<IpBan> {
+        self.0.read().await.banned_ips.clone()
+    }
+
+    /// False if already banned
+    pub async fn ban_player(&self, ban: PlayerBan) -> McResult<bool> {
+        let mut lists = self.0.write().await;
+        let now = OffsetDateTime::now_utc();
+        if lists
+            .banned_players
+            .iter()
+            .any(|b| b.uuid == ban.uuid && !b.ban.is_expired(now))
+        {
+            return Ok(false);
+        }
+
+        lists.banned_players.retain(|b| b.uuid != ban.uuid);
+        lists.banned_players.push(ban);
+        save_list(&lists.dir.join(BANNED_PLAYERS_FILE), &lists.banned_players)?;
+        Ok(true)
+    }
+
+    /// False if not banned
+    pub async fn pardon_player(&self, name: &str) -> McResult<bool> {
+        let mut lists = self.0.write().await;
+        let count = lists.banned_players.len();
+        lists
+            .banned_players
+            .retain(|b| !b.name.eq_ignore_ascii_case(name));
+        if lists.banned_players.len() == count {
+            return Ok(false);
+        }
+
+        save_list(&lists.dir.join(BANNED_PLAYERS_FILE), &lists.banned_players)?;
+        Ok(true)
+    }
+
+    /// False if already banned
+    pub async fn ban_ip(&self, ban: IpBan) -> McResult<bool> {
+        let mut lists = self.0.write().await;
+        let now = OffsetDateTime::now_utc();
+        if lists
+            .banned_ips
+            .iter()
+            .any(|b| b.ip == ban.ip && !b.ban.is_expired(now))
+        {
+            return Ok(false);
+        }
+
+        lists.banned_ips.retain(|b| b.ip != ban.ip);
+        lists.banned_ips.push(ban);
+        save_list(&lists.dir.join(BANNED_IPS_FILE), &lists.banned_ips)?;
+        Ok(true)
+    }
+
+    /// False if not banned
+    pub async fn pardon_ip(&self, ip: IpAddr) -> McResult<bool> {
+        let mut lists = self.0.write().await;
+        let count = lists.banned_ips.len();
+        lists.banned_ips.retain(|b| b.ip != ip);
+        if lists.banned_ips.len() == count {
+            return Ok(false);
+        }
+
+        save_list(&lists.dir.join(BANNED_IPS_FILE), &lists.banned_ips)?;
+        Ok(true)
+    }
+
+    /// Permission level of the player, 0 if they're not an operator
+    pub async fn op_level(&self, uuid: &Uuid) -> u8 {
+        self.0
+            .read()
+            .await
+            .ops
+            .iter()
+            .find(|op| op.uuid == *uuid)
+            .map(|op| op.level)
+            .unwrap_or(0)
+    }
+
+    /// False if already an operator at this level
+    pub async fn op(&self, entry: OpEntry) -> McResult<bool> {
+        let mut lists = self.0.write().await;
+        if lists.ops.contains(&entry) {
+            return Ok(false);
+        }
+
+        lists.ops.retain(|op| op.uuid != entry.uuid);
+        lists.ops.push(entry);
+        save_list(&lists.dir.join(OPS_FILE), &lists.ops)?;
+        Ok(true)
+    }
+
+    /// The UUID of the removed operator, None if they weren't one
+    pub async fn deop(&self, name: &str) -> McResult<Option<Uuid>> {
+        let mut lists = self.0.write().await;
+        let idx = match lists
+            .ops
+            .iter()
+            .position(|op| op.name.eq_ignore_ascii_case(name))
+        {
+            Some(idx) => idx,
+            None => return Ok(None),
+        };
+
+        let removed = lists.ops.remove(idx);
+        save_list(&lists.dir.join(OPS_FILE), &lists.ops)?;
+        Ok(Some(removed.uuid))
+    }
+}
+
+impl Lists {
+    fn load(dir: PathBuf, whitelist_enabled: bool) -> McResult<Self> {
+        Ok(Self {
+            whitelist_enabled,
+            whitelist: load_list(&dir.join(WHITELIST_FILE))?,
+            banned_players: load_list(&dir.join(BANNED_PLAYERS_FILE))?,
+            banned_ips: load_list(&dir.join(BANNED_IPS_FILE))?,
+            ops: load_list(&dir.join(OPS_FILE))?,
+            dir,
+        })
+    }
+}
+
+fn format_date(date: OffsetDateTime) -> String {
+    date.format(DATE_FORMAT)
+}
+
+fn load_list<T: DeserializeOwned>(path: &Path) -> McResult<Vec<T>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            debug!("loading {}", path.display());
+            serde_json::from_str(&contents).map_err(|e| McError::BadAccessList {
+                path: path.to_owned(),
+                reason: e.to_string(),
+            })
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn save_list<T: Serialize>(path: &Path, list: &[T]) -> McResult<()> {
+    let json = serde_json::to_string_pretty(list).map_err(|e| McError::BadAccessList {
+        path: path.to_owned(),
+        reason: e.to_string(),
+    })?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use async_std::task;
+
+    use super::*;
+
+    #[test]
+    fn check_login() {
+        let dir = std::env::temp_dir().join(format!("mc-access-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let notch = Uuid::from_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+        let jeb = Uuid::from_str("853c80ef-3c37-49fd-aa49-938b674adae6").unwrap();
+        let home: IpAddr = "127.0.0.1".parse().unwrap();
+        let away: IpAddr = "10.0.0.1".parse().unwrap();
+
+        // vanilla format, with an expired ban
+        fs::write(
+            dir.join(BANNED_PLAYERS_FILE),
+            r#"[{"uuid": "853c80ef-3c37-49fd-aa49-938b674adae6", "name": "jeb_",
+                 "created": "2020-10-17 12:00:00 +0100", "source": "Server",
+                 "expires": "2020-10-18 12:00:00 +0100", "reason": "Griefing"}]"#,
+        )
+        .unwrap();
+        let _ = fs::remove_file(dir.join(BANNED_IPS_FILE));
+        let _ = fs::remove_file(dir.join(WHITELIST_FILE));
+        let _ = fs::remove_file(dir.join(OPS_FILE));
+
+        task::block_on(async {
+            let lists = AccessLists::load(&dir, false).unwrap();
+            assert!(lists.check_login(&jeb, "jeb_", home).await.is_ok());
+
+            let ban = PlayerBan {
+                uuid: notch,
+                name: "Notch".to_owned(),
+                ban: Ban::new("Server", Some("Testing")),
+            };
+            assert!(lists.ban_player(ban.clone()).await.unwrap());
+            assert!(!lists.ban_player(ban).await.unwrap());
+            match lists.check_login(&notch, "Notch", home).await {
+                Err(McError::LoginDenied(msg)) => {
+                    assert_eq!(msg, "You are banned from this server.\nReason: Testing")
+                }
+                _ => panic!("expected ban"),
+            }
+
+            let ip_ban = IpBan {
+                ip: away,
+                ban: Ban::new("Server", None),
+            };
+            assert!(lists.ban_ip(ip_ban).await.unwrap());
+            assert!(lists.check_login(&jeb, "jeb_", away).await.is_err());
+
+            lists.set_whitelist_enabled(true).await;
+            assert!(lists.check_login(&jeb, "jeb_", home).await.is_err());
+            let entry = WhitelistEntry {
+                uuid: jeb,
+                name: "jeb_".to_owned(),
+            };
+            assert!(lists.whitelist_add(entry).await.unwrap());
+            assert!(lists.check_login(&jeb, "jeb_", home).await.is_ok());
+
+            // changes are persisted
+            let reloaded = AccessLists::load(&dir, true).unwrap();
+            assert!(reloaded.check_login(&notch, "Notch", home).await.is_err());
+            assert!(reloaded.check_login(&jeb, "jeb_", away).await.is_err());
+            assert!(reloaded.check_login(&jeb, "jeb_", home).await.is_ok());
+
+            assert!(reloaded.pardon_player("notch").await.unwrap());
+            assert!(reloaded.pardon_ip(away).await.unwrap());
+            assert!(!reloaded.pardon_ip(away).await.unwrap());
+            assert_eq!(reloaded.banned_players().await.len(), 1); // the expired one
+
+            let op = OpEntry {
+                uuid: notch,
+                name: "Notch".to_owned(),
+                level: 4,
+                bypasses_player_limit: false,
+            };
+            assert!(reloaded.op(op.clone()).await.unwrap());
+            assert!(!reloaded.op(op).await.unwrap());
+            let reloaded = AccessLists::load(&dir, true).unwrap();
+            assert_eq!(reloaded.op_level(&notch).await, 4);
+            assert_eq!(reloaded.op_level(&jeb).await, 0);
+            assert_eq!(reloaded.deop("notch").await.unwrap(), Some(notch));
+            assert_eq!(reloaded.deop("notch").await.unwrap(), None);
+            assert_eq!(reloaded.op_level(&notch).await, 0);
+        });
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
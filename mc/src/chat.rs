@@ -0,0 +1,67 @@
+//! JSON chat components
+
+use serde_json::{json, Value};
+
+/// Longest message the client will send
+pub const MAX_MESSAGE_LENGTH: usize = 256;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChatPosition {
+    Chat = 0,
+    System = 1,
+    /// Above the hotbar
+    GameInfo = 2,
+}
+
+pub fn text(text: &str) -> Value {
+    json!({ "text": text })
+}
+
+pub fn colored(text: &str, color: &str) -> Value {
+    json!({ "text": text, "color": color })
+}
+
+/// Translated by the client, with arguments substituted in
+pub fn translate(key: &str, with: Vec<Value>) -> Value {
+    json!({ "translate": key, "with": with })
+}
+
+/// `<name> message`
+pub fn player_message(name: &str, message: &str) -> Value {
+    translate("chat.type.text", vec![text(name), text(message)])
+}
+
+pub fn player_joined(name: &str) -> Value {
+    let mut component = translate("multiplayer.player.joined", vec![text(name)]);
+    component["color"] = "yellow".into();
+    component
+}
+
+pub fn player_left(name: &str) -> Value {
+    let mut component = translate("multiplayer.player.left", vec![text(name)]);
+    component["color"] = "yellow".into();
+    component
+}
+
+/// Formatting codes and control characters are not allowed in chat, as in vanilla
+pub fn is_allowed_char(c: char) -> bool {
+    c != '§' && c >= ' ' && c != '\u{7f}'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn components() {
+        assert_eq!(
+            player_message("Steve", "hi").to_string(),
+            r#"{"translate":"chat.type.text","with":[{"text":"Steve"},{"text":"hi"}]}"#
+        );
+        assert_eq!(player_left("Alex")["color"], "yellow");
+
+        assert!("hello there!".chars().all(is_allowed_char));
+        assert!(!"§cred".chars().all(is_allowed_char));
+        assert!(!"new\nline".chars().all(is_allowed_char));
+    }
+}
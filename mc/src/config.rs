@@ -1,2 +1,343 @@
-// TODO actual config
-pub const ONLINE_MODE: bool = false;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use log::*;
+
+use crate::error::{McError, McResult};
+
+/// Server settings, loaded from a `server.properties` compatible file
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Empty to bind to all interfaces
+    pub bind_address: String,
+    pub port: u16,
+    pub online_mode: bool,
+    /// Only players on the whitelist may join
+    pub whitelist: bool,
+    pub motd: String,
+    pub max_players: u32,
+    /// In chunks
+    pub view_distance: u8,
+    /// Packets of at least this many bytes are compressed, None to disable compression
+    pub compression_threshold: Option<usize>,
+    pub icon_path: Option<PathBuf>,
+    /// Directory of the world to load
+    pub level_name: String,
+    /// Generator for new worlds, `default` or `flat`
+    pub level_type: String,
+    /// Layer preset for flat worlds, e.g. `minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block`
+    pub generator_settings: String,
+    /// Seed for new worlds, random if empty
+    pub level_seed: String,
+    /// Kick message sent to players when the server stops
+    pub shutdown_message: String,
+    /// Base URL of the session server used to authenticate players in online mode
+    pub session_server: String,
+}
+
+const VIEW_DISTANCE_RANGE: std::ops::RangeInclusive<u8> = 2..=32;
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: String::new(),
+            port: 25565,
+            online_mode: false,
+            whitelist: false,
+            motd: "A Minecraft Server".to_owned(),
+            max_players: 20,
+            view_distance: 10,
+            compression_threshold: Some(256),
+            icon_path: Some(PathBuf::from("server-icon.png")),
+            level_name: "world".to_owned(),
+            level_type: "default".to_owned(),
+            generator_settings: String::new(),
+            level_seed: String::new(),
+            shutdown_message: "Server closed".to_owned(),
+            session_server: "https://sessionserver.mojang.com".to_owned(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Loads the config at the given path, writing out the defaults first if it doesn't exist
+    pub fn load_or_create(path: impl AsRef<Path>) -> McResult<Self> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                info!("loading config from {}", path.display());
+                Self::parse(&contents)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                info!("writing default config to {}", path.display());
+                let config = Self::default();
+                fs::write(path, config.to_properties())?;
+                Ok(config)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Address to pass to the listener
+    pub fn bind_host(&self) -> &str {
+        if self.bind_address.is_empty() {
+            "0.0.0.0"
+        } else {
+            &self.bind_address
+        }
+    }
+
+    pub fn parse(contents: &str) -> McResult<Self> {
+        let mut config = Self::default();
+
+        for (key, value) in properties(contents) {
+            match key.as_str() {
+                "server-ip" => config.bind_address = value,
+                "server-port" => config.port = parse_value(&key, &value)?,
+                "online-mode" => config.online_mode = parse_value(&key, &value)?,
+                "white-list" => config.whitelist = parse_value(&key, &value)?,
+                "motd" => config.motd = value,
+                "max-players" => config.max_players = parse_value(&key, &value)?,
+                "view-distance" => {
+                    let distance = parse_value(&key, &value)?;
+                    if !VIEW_DISTANCE_RANGE.contains(&distance) {
+                        return Err(McError::BadConfig {
+                            key,
+                            reason: format!(
+                                "{} is out of range {}-{}",
+                                distance,
+                                VIEW_DISTANCE_RANGE.start(),
+                                VIEW_DISTANCE_RANGE.end()
+                            ),
+                        });
+                    }
+                    config.view_distance = distance;
+                }
+                "network-compression-threshold" => {
+                    // negative disables compression, as in vanilla
+                    let threshold: i32 = parse_value(&key, &value)?;
+                    config.compression_threshold = if threshold < 0 {
+                        None
+                    } else {
+                        Some(threshold as usize)
+                    };
+                }
+                "server-icon" => {
+                    config.icon_path = if value.is_empty() {
+                        None
+                    } else {
+                        Some(PathBuf::from(value))
+                    }
+                }
+                "level-name" => config.level_name = value,
+                "level-type" => config.level_type = value,
+                "generator-settings" => config.generator_settings = value,
+                "level-seed" => config.level_seed = value,
+                "shutdown-message" => config.shutdown_message = value,
+                "session-server" => config.session_server = value,
+                _ => debug!("ignoring unsupported config key '{}'", key),
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn to_properties(&self) -> String {
+        let mut out = String::from("#Minecraft server properties\n");
+
+        let threshold = self
+            .compression_threshold
+            .map(|t| t as i64)
+            .unwrap_or(-1)
+            .to_string();
+        let icon = self
+            .icon_path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+
+        let entries = [
+            ("server-ip", self.bind_address.clone()),
+            ("server-port", self.port.to_string()),
+            ("online-mode", self.online_mode.to_string()),
+            ("white-list", self.whitelist.to_string()),
+            ("motd", self.motd.clone()),
+            ("max-players", self.max_players.to_string()),
+            ("view-distance", self.view_distance.to_string()),
+            ("network-compression-threshold", threshold),
+            ("server-icon", icon),
+            ("level-name", self.level_name.clone()),
+            ("level-type", self.level_type.clone()),
+            ("generator-settings", self.generator_settings.clone()),
+            ("level-seed", self.level_seed.clone()),
+            ("shutdown-message", self.shutdown_message.clone()),
+            ("session-server", self.session_server.clone()),
+        ];
+
+        for (key, value) in entries.iter() {
+            let _ = writeln!(out, "{}={}", key, escape(value));
+        }
+
+        out
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> McResult<T>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e: T::Err| McError::BadConfig {
+            key: key.to_owned(),
+            reason: format!("'{}' is invalid: {}", value, e),
+        })
+}
+
+/// Key-value pairs from a Java properties file, skipping comments and blank lines
+fn properties(contents: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    contents.lines().filter_map(|line| {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            return None;
+        }
+
+        // split on the first unescaped separator
+        let mut escaped = false;
+        let split = line.char_indices().find(|&(_, c)| {
+            let is_separator = !escaped && (c == '=' || c == ':');
+            escaped = !escaped && c == '\\';
+            is_separator
+        });
+
+        let (key, value) = match split {
+            Some((idx, _)) => (&line[..idx], &line[idx + 1..]),
+            None => (line, ""),
+        };
+
+        Some((unescape(key.trim_end()), unescape(value.trim_start())))
+    })
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                match u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                {
+                    Some(c) => out.push(c),
+                    None => {
+                        out.push_str("\\u");
+                        out.push_str(&hex);
+                    }
+                }
+            }
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+
+    out
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '=' => out.push_str("\\="),
+            ':' => out.push_str("\\:"),
+            '\n' => out.push_str("\\n"),
+            c if !c.is_ascii() && (c as u32) <= 0xffff => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_vanilla() {
+        let config = ServerConfig::parse(
+            "#Minecraft server properties\n\
+             #Sat Oct 17 12:00:00 BST 2020\n\
+             spawn-protection=16\n\
+             server-ip=\n\
+             server-port=25566\n\
+             online-mode=true\n\
+             white-list=true\n\
+             motd=A \\u00A7cred\\u00A7r server\\: hi\n\
+             max-players=50\n\
+             view-distance=8\n\
+             network-compression-threshold=-1\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.bind_host(), "0.0.0.0");
+        assert_eq!(config.port, 25566);
+        assert!(config.online_mode);
+        assert!(config.whitelist);
+        assert_eq!(config.motd, "A §cred§r server: hi");
+        assert_eq!(config.max_players, 50);
+        assert_eq!(config.view_distance, 8);
+        assert_eq!(config.compression_threshold, None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let config = ServerConfig {
+            motd: "quotes \" and = signs: §6gold".to_owned(),
+            compression_threshold: Some(64),
+            icon_path: None,
+            shutdown_message: "Restarting, back soon!".to_owned(),
+            session_server: "http://localhost:8080".to_owned(),
+            ..ServerConfig::default()
+        };
+
+        let parsed = ServerConfig::parse(&config.to_properties()).unwrap();
+        assert_eq!(parsed.motd, config.motd);
+        assert_eq!(parsed.shutdown_message, config.shutdown_message);
+        assert_eq!(parsed.session_server, config.session_server);
+        assert_eq!(parsed.compression_threshold, Some(64));
+        assert_eq!(parsed.icon_path, None);
+    }
+
+    #[test]
+    fn invalid_values() {
+        for bad in &[
+            "server-port=70000",
+            "online-mode=yes",
+            "max-players=-1",
+            "view-distance=100",
+            "network-compression-threshold=lots",
+        ] {
+            match ServerConfig::parse(bad) {
+                Err(McError::BadConfig { key, .. }) => assert!(bad.starts_with(&key)),
+                _ => panic!("expected error for '{}'", bad),
+            }
+        }
+    }
+}
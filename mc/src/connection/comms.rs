@@ -1,25 +1,67 @@
+use crate::connection::compression;
+use crate::connection::legacy::{self, LegacyPing, LEGACY_PING};
+use crate::packet::write_var_int;
 use crate::prelude::*;
-use async_std::io::{Cursor, ErrorKind};
-use async_std::sync::{Arc, RwLock};
+use async_std::io::{self, Cursor, ErrorKind};
+use async_std::sync::Arc;
+use futures::task::{Context, Poll};
 use futures::{Sink, SinkExt};
 use minecraft_server_protocol::types::*;
-use openssl::symm::{encrypt, Cipher};
-use std::ops::Deref;
+use openssl::symm::{Cipher, Crypter, Mode};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// How long to wait for the rest of a legacy ping, the oldest clients only send a single byte
+const LEGACY_PING_TIMEOUT: Duration = Duration::from_millis(200);
 
 // TODO arena allocator
-pub struct ClientBoundPacket(Box<dyn ClientBound>);
+pub struct ClientBoundPacket {
+    packet: PacketKind,
+
+    /// Compression threshold to switch to once this packet has been sent
+    enables_compression: Option<usize>,
+
+    /// Connection is closed once this packet has been sent
+    disconnects: bool,
+}
+
+enum PacketKind {
+    Generated(Box<dyn ClientBound>),
+
+    /// Encoded by hand, body excludes the packet ID
+    Raw {
+        id: i32,
+        body: Vec<u8>,
+    },
+}
 
 impl<P: ClientBound + 'static> From<P> for ClientBoundPacket {
     fn from(packet: P) -> Self {
-        Self(Box::new(packet))
+        Self {
+            packet: PacketKind::Generated(Box::new(packet)),
+            enables_compression: None,
+            disconnects: false,
+        }
     }
 }
 
-impl Deref for ClientBoundPacket {
-    type Target = dyn ClientBound;
+impl ClientBoundPacket {
+    pub(crate) fn raw(id: i32, body: Vec<u8>) -> Self {
+        Self {
+            packet: PacketKind::Raw { id, body },
+            enables_compression: None,
+            disconnects: false,
+        }
+    }
 
-    fn deref(&self) -> &Self::Target {
-        self.0.deref()
+    /// Marks this as the last packet to send before closing the connection, e.g. a kick
+    pub fn disconnecting(self) -> Self {
+        Self {
+            disconnects: true,
+            ..self
+        }
     }
 }
 
@@ -29,17 +71,36 @@ impl<S: Sink<ClientBoundPacket> + Unpin + Send + Sync> ResponseSink for S {}
 
 pub enum Encryption {
     Plaintext,
+
+    /// AES-128-CFB8 with a persistent cipher per direction, their state carries across packets
     Encrypted {
-        shared_secret: Vec<u8>,
-        cipher: Cipher,
+        encrypter: Crypter,
+        decrypter: Crypter,
     },
 }
 
-pub type CommsEncryption = Arc<RwLock<Encryption>>;
+/// Never held across an await, so a sync mutex is fine and can be used from `poll_read`
+pub type CommsEncryption = Arc<Mutex<Encryption>>;
+
+/// Packets of at least this many bytes are compressed, negative if compression is disabled.
+/// Shared between the reader and writer halves.
+type CommsCompression = Arc<AtomicI32>;
 
 pub struct ActiveComms<S: McStream> {
     encryption: CommsEncryption,
-    stream: S,
+    compression: CommsCompression,
+    stream: DecryptingStream<S>,
+
+    /// First byte of the connection, read while checking for a legacy ping
+    pending_byte: Option<u8>,
+}
+
+/// Decrypts every byte read from the inner stream once encryption has been enabled, including
+/// the length and packet ID VarInts. Writes pass through untouched.
+struct DecryptingStream<S: McStream> {
+    inner: S,
+    encryption: CommsEncryption,
+    decrypt_buf: Vec<u8>,
 }
 
 pub struct CommsRef<R: ResponseSink> {
@@ -64,12 +125,30 @@ impl<R: ResponseSink> CommsRef<R> {
             .map_err(|_| McError::SinkUnknown)
     }
 
-    pub async fn upgrade(&self, shared_secret: Vec<u8>) {
-        let mut guard = self.encryption.write().await;
-        *guard = Encryption::Encrypted {
-            shared_secret,
-            cipher: Cipher::aes_128_cfb8(),
-        }
+    /// Sends Set Compression, after which every packet in both directions uses the compressed
+    /// framing
+    pub(crate) async fn enable_compression(&mut self, threshold: usize) -> McResult<()> {
+        let packet = crate::packet::login::client::Compress {
+            threshold: (threshold as i32).into(),
+        };
+
+        let packet = ClientBoundPacket {
+            enables_compression: Some(threshold),
+            ..packet.into()
+        };
+
+        self.response_sink
+            .send(packet)
+            .await
+            .map_err(|_| McError::SinkUnknown)
+    }
+
+    pub fn upgrade(&self, shared_secret: Vec<u8>) -> McResult<()> {
+        let encrypted = Encryption::new_encrypted(&shared_secret)?;
+
+        let mut guard = lock_encryption(&self.encryption)?;
+        *guard = encrypted;
+        Ok(())
     }
 
     pub async fn close(&mut self) -> McResult<()> {
@@ -80,61 +159,254 @@ impl<R: ResponseSink> CommsRef<R> {
     }
 }
 
+fn lock_encryption(encryption: &CommsEncryption) -> io::Result<MutexGuard<'_, Encryption>> {
+    encryption
+        .lock()
+        .map_err(|_| io::Error::new(ErrorKind::Other, "encryption state poisoned"))
+}
+
 impl Encryption {
-    async fn serialize_packet(&self, packet: ClientBoundPacket) -> McResult<Box<[u8]>> {
-        let plaintext = {
-            let mut buf = vec![0u8; packet.full_size()];
-            let mut cursor = Cursor::new(buf.as_mut_slice());
-            packet.write_packet(&mut cursor).await?;
-            buf
+    fn new_encrypted(shared_secret: &[u8]) -> McResult<Self> {
+        // shared secret is used as both the key and IV
+        let crypter = |mode| {
+            Crypter::new(
+                Cipher::aes_128_cfb8(),
+                mode,
+                shared_secret,
+                Some(shared_secret),
+            )
         };
 
+        Ok(Encryption::Encrypted {
+            encrypter: crypter(Mode::Encrypt)?,
+            decrypter: crypter(Mode::Decrypt)?,
+        })
+    }
+
+    fn encrypt_packet(&mut self, plaintext: Vec<u8>) -> McResult<Box<[u8]>> {
         match self {
             Encryption::Plaintext => Ok(plaintext.into_boxed_slice()),
-            Encryption::Encrypted {
-                shared_secret,
-                cipher,
-            } => encrypt(*cipher, &shared_secret, Some(&shared_secret), &plaintext)
-                .map(Vec::into_boxed_slice)
-                .map_err(McError::OpenSSL),
+            Encryption::Encrypted { encrypter, .. } => {
+                // openssl insists on room for an extra block in the output
+                let mut ciphertext =
+                    vec![0u8; plaintext.len() + Cipher::aes_128_cfb8().block_size()];
+                let n = encrypter.update(&plaintext, &mut ciphertext)?;
+                debug_assert_eq!(n, plaintext.len(), "cfb8 should encrypt byte for byte");
+                ciphertext.truncate(n);
+                Ok(ciphertext.into_boxed_slice())
+            }
+        }
+    }
+
+    /// Decrypts in place if encrypted, using `scratch` as the output buffer
+    fn decrypt_in_place(&mut self, buf: &mut [u8], scratch: &mut Vec<u8>) -> io::Result<()> {
+        if let Encryption::Encrypted { decrypter, .. } = self {
+            // openssl insists on room for an extra block in the output
+            scratch.resize(buf.len() + Cipher::aes_128_cfb8().block_size(), 0);
+            let n = decrypter
+                .update(buf, scratch)
+                .map_err(|e| io::Error::new(ErrorKind::Other, e))?;
+            debug_assert_eq!(n, buf.len(), "cfb8 should decrypt byte for byte");
+            buf.copy_from_slice(&scratch[..n]);
         }
+
+        Ok(())
     }
 }
 
-impl<S: McStream> ActiveComms<S> {
-    pub fn new(reader: S, writer: S) -> (Self, Self, CommsEncryption) {
-        let enc = Arc::new(RwLock::new(Encryption::Plaintext));
+/// Full uncompressed packet including length prefix
+async fn serialize_packet(packet: &ClientBoundPacket) -> McResult<Vec<u8>> {
+    match &packet.packet {
+        PacketKind::Generated(packet) => {
+            let mut buf = vec![0u8; packet.full_size()];
+            let mut cursor = Cursor::new(buf.as_mut_slice());
+            packet.write_packet(&mut cursor).await?;
+            Ok(buf)
+        }
+        PacketKind::Raw { id, body } => {
+            let mut id_buf = Vec::with_capacity(5);
+            write_var_int(&mut id_buf, *id);
 
-        let r = Self {
-            encryption: enc.clone(),
-            stream: reader,
+            let mut buf = Vec::with_capacity(id_buf.len() + body.len() + 5);
+            write_var_int(&mut buf, (id_buf.len() + body.len()) as i32);
+            buf.extend_from_slice(&id_buf);
+            buf.extend_from_slice(body);
+            Ok(buf)
+        }
+    }
+}
+
+impl<S: McStream> Read for DecryptingStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let n = match Pin::new(&mut self.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(n)) => n,
+            other => return other,
         };
 
-        let w = Self {
+        // check the encryption state only once the bytes have actually arrived, so anything sent
+        // after the upgrade is decrypted even if this read started beforehand
+        let this = &mut *self;
+        let mut encryption = lock_encryption(&this.encryption)?;
+        encryption.decrypt_in_place(&mut buf[..n], &mut this.decrypt_buf)?;
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<S: McStream> Write for DecryptingStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+impl<S: McStream> ActiveComms<S> {
+    pub fn new(reader: S, writer: S) -> (Self, Self, CommsEncryption) {
+        let enc = Arc::new(Mutex::new(Encryption::Plaintext));
+        let compression = Arc::new(AtomicI32::new(-1));
+
+        let wrap = |stream| Self {
             encryption: enc.clone(),
-            stream: writer,
+            compression: compression.clone(),
+            stream: DecryptingStream {
+                inner: stream,
+                encryption: enc.clone(),
+                decrypt_buf: Vec::new(),
+            },
+            pending_byte: None,
         };
 
+        let (r, w) = (wrap(reader), wrap(writer));
         (r, w, enc)
     }
 
+    fn compression_threshold(&self) -> Option<usize> {
+        let threshold = self.compression.load(Ordering::Acquire);
+        if threshold >= 0 {
+            Some(threshold as usize)
+        } else {
+            None
+        }
+    }
+
     //noinspection RsUnresolvedReference - idk why write_all isn't found by CLion
     pub async fn send_packet(&mut self, packet: ClientBoundPacket) -> McResult<()> {
-        // let enc = self.encryption.read().await;
-        // let blob = enc.serialize_packet(packet)?;
         // TODO streamify?
-        let enc = self.encryption.read().await;
-        let serialized = enc.serialize_packet(packet).await?;
+        let mut plaintext = serialize_packet(&packet).await?;
+        if let Some(threshold) = self.compression_threshold() {
+            plaintext = compression::compress_frame(&plaintext, threshold)?;
+        }
+
+        if let Some(threshold) = packet.enables_compression {
+            // the client won't send anything until it receives this packet, so the reader can
+            // safely switch over now
+            debug!("enabling compression with threshold {}", threshold);
+            self.compression.store(threshold as i32, Ordering::Release);
+        }
+
+        let serialized = lock_encryption(&self.encryption)?.encrypt_packet(plaintext)?;
         self.stream
             .write_all(&serialized)
             .await
-            .map_err(McError::Io)
+            .map_err(McError::Io)?;
+
+        if packet.disconnects {
+            self.stream.flush().await.map_err(McError::Io)?;
+            return Err(McError::PleaseDisconnect);
+        }
+
+        Ok(())
+    }
+
+    /// Checks for a pre-1.7 server list ping, which doesn't follow the modern packet format. Must
+    /// be called before any packets are read.
+    pub async fn read_legacy_ping(&mut self) -> McResult<Option<LegacyPing>> {
+        let first = match self.read_byte().await {
+            Err(McError::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                return Err(McError::PleaseDisconnect)
+            }
+            res => res?,
+        };
+
+        if first != LEGACY_PING {
+            // must be the start of a modern handshake
+            self.pending_byte = Some(first);
+            return Ok(None);
+        }
+
+        // 1.4 onwards follows up immediately with 0x01
+        match async_std::future::timeout(LEGACY_PING_TIMEOUT, self.read_byte()).await {
+            Err(_) => return Ok(Some(LegacyPing::Beta)),
+            Ok(Ok(0x01)) => {}
+            Ok(Ok(other)) => return Err(McError::BadLegacyPing(other)),
+            Ok(Err(e)) => return Err(e),
+        }
+
+        // 1.6 follows up with a MC|PingHost plugin message, which must be drained so the socket
+        // isn't reset on close
+        match async_std::future::timeout(LEGACY_PING_TIMEOUT, self.read_byte()).await {
+            Err(_) => {}
+            Ok(Ok(0xfa)) => {
+                let channel_len = self.read_u16().await? as usize * 2; // utf16
+                let mut channel = vec![0u8; channel_len];
+                self.stream.read_exact(&mut channel).await?;
+
+                let data_len = self.read_u16().await? as usize;
+                let mut data = vec![0u8; data_len];
+                self.stream.read_exact(&mut data).await?;
+            }
+            Ok(Ok(other)) => return Err(McError::BadLegacyPing(other)),
+            Ok(Err(e)) => return Err(e),
+        }
+
+        Ok(Some(LegacyPing::Release))
+    }
+
+    /// Writes the legacy ping response kick string. The connection should be closed afterwards.
+    pub async fn send_legacy_response(&mut self, response: &str) -> McResult<()> {
+        let encoded = legacy::encode_response(response);
+        self.stream.write_all(&encoded).await?;
+        self.stream.flush().await.map_err(McError::Io)
+    }
+
+    async fn read_byte(&mut self) -> McResult<u8> {
+        let mut buf = [0u8; 1];
+        self.stream.read_exact(&mut buf).await?;
+        Ok(buf[0])
+    }
+
+    async fn read_u16(&mut self) -> McResult<u16> {
+        let mut buf = [0u8; 2];
+        self.stream.read_exact(&mut buf).await?;
+        Ok(u16::from_be_bytes(buf))
     }
 
     //noinspection RsUnresolvedReference - idk why read_exact isn't found by CLion
     pub async fn read_packet(&mut self) -> McResult<PacketBody> {
-        // TODO DECRYPT STREAM?!
-        let mut length = match VarIntField::read_field(&mut self.stream).await {
+        let length = match self.pending_byte.take() {
+            Some(first) => {
+                let first = [first];
+                let mut chained = (&first[..]).chain(&mut self.stream);
+                VarIntField::read_field(&mut chained).await
+            }
+            None => VarIntField::read_field(&mut self.stream).await,
+        };
+
+        let length = match length {
             Err(PacketError::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => {
                 debug!("eof");
                 return Err(McError::PleaseDisconnect);
@@ -150,6 +422,36 @@ impl<S: McStream> ActiveComms<S> {
 
         debug!("packet length={}", length);
 
+        match self.compression_threshold() {
+            None => self.read_uncompressed_body(length).await,
+            Some(threshold) => {
+                let data_length = VarIntField::read_field(&mut self.stream).await?;
+
+                let compressed_length = length - data_length.size() as i32;
+                let mut recv_buf = vec![0u8; compressed_length.max(0) as usize];
+                self.stream
+                    .read_exact(&mut recv_buf)
+                    .await
+                    .map_err(McError::Io)?;
+
+                let data = match data_length.value() {
+                    0 => recv_buf, // below threshold, not compressed
+                    n => compression::decompress(&recv_buf, n as usize, threshold)?,
+                };
+
+                let mut cursor = Cursor::new(&data);
+                let packet_id = VarIntField::read_field(&mut cursor).await?;
+                debug!("packet id={:#x}", packet_id.value());
+
+                Ok(PacketBody {
+                    id: packet_id.value(),
+                    body: data[packet_id.size()..].to_vec(),
+                })
+            }
+        }
+    }
+
+    async fn read_uncompressed_body(&mut self, mut length: i32) -> McResult<PacketBody> {
         let packet_id = {
             let varint = VarIntField::read_field(&mut self.stream).await?;
             length -= varint.size() as i32; // length includes packet id
@@ -172,3 +474,62 @@ impl<S: McStream> ActiveComms<S> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_await_test::async_test;
+    use openssl::symm::{decrypt, encrypt};
+
+    const SECRET: &[u8] = b"sixteen byte key";
+
+    fn packets() -> Vec<Vec<u8>> {
+        (0..200u32)
+            .map(|i| (0..(i * 7) % 300 + 1).map(|b| (b ^ i) as u8).collect())
+            .collect()
+    }
+
+    #[test]
+    fn encrypt_carries_state_across_packets() {
+        let mut encryption = Encryption::new_encrypted(SECRET).unwrap();
+
+        let mut plaintext = Vec::new();
+        let mut ciphertext = Vec::new();
+        for packet in packets() {
+            plaintext.extend_from_slice(&packet);
+            let encrypted = encryption.encrypt_packet(packet).unwrap();
+            ciphertext.extend_from_slice(&encrypted);
+        }
+
+        // a reference decryptor sees one continuous stream
+        let decrypted = decrypt(Cipher::aes_128_cfb8(), SECRET, Some(SECRET), &ciphertext).unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[async_test]
+    async fn decrypt_carries_state_across_reads() {
+        let plaintext = packets().concat();
+        let ciphertext = encrypt(Cipher::aes_128_cfb8(), SECRET, Some(SECRET), &plaintext).unwrap();
+
+        let mut stream = DecryptingStream {
+            inner: Cursor::new(ciphertext),
+            encryption: Arc::new(Mutex::new(Encryption::new_encrypted(SECRET).unwrap())),
+            decrypt_buf: Vec::new(),
+        };
+
+        // read in awkward chunk sizes
+        let mut decrypted = Vec::with_capacity(plaintext.len());
+        for chunk_size in [1, 2, 5, 13, 64, 250].iter().cycle() {
+            let remaining = plaintext.len() - decrypted.len();
+            if remaining == 0 {
+                break;
+            }
+
+            let mut buf = vec![0u8; remaining.min(*chunk_size)];
+            stream.read_exact(&mut buf).await.unwrap();
+            decrypted.extend_from_slice(&buf);
+        }
+
+        assert_eq!(decrypted, plaintext);
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::packet::{read_var_int, write_var_int};
+use crate::prelude::*;
+
+/// Largest uncompressed packet the client is allowed to send
+const MAX_DATA_LENGTH: usize = 2_097_152;
+
+/// Re-frames a serialized uncompressed packet (`length | id | body`) into the compressed format
+/// (`length | data length | zlib(id | body)`). Packets under the threshold are left uncompressed
+/// with a data length of 0.
+pub fn compress_frame(frame: &[u8], threshold: usize) -> McResult<Vec<u8>> {
+    let (_, prefix_len) = read_var_int(frame).ok_or(McError::MalformedPacket(frame.len()))?;
+    let data = &frame[prefix_len..];
+
+    let mut payload = Vec::with_capacity(data.len() + 5);
+    if data.len() >= threshold {
+        write_var_int(&mut payload, data.len() as i32);
+
+        let mut encoder = ZlibEncoder::new(payload, Compression::default());
+        encoder.write_all(data)?;
+        payload = encoder.finish()?;
+    } else {
+        write_var_int(&mut payload, 0);
+        payload.extend_from_slice(data);
+    }
+
+    let mut framed = Vec::with_capacity(payload.len() + 5);
+    write_var_int(&mut framed, payload.len() as i32);
+    framed.extend_from_slice(&payload);
+    Ok(framed)
+}
+
+/// Inflates the `id | body` of a compressed packet, validating the claimed data length
+pub fn decompress(compressed: &[u8], data_length: usize, threshold: usize) -> McResult<Vec<u8>> {
+    if data_length < threshold || data_length > MAX_DATA_LENGTH {
+        return Err(McError::MalformedPacket(data_length));
+    }
+
+    let mut data = Vec::with_capacity(data_length);
+    ZlibDecoder::new(compressed)
+        .take(data_length as u64 + 1)
+        .read_to_end(&mut data)?;
+
+    if data.len() != data_length {
+        return Err(McError::BadDecompressedLength {
+            expected: data_length,
+            actual: data.len(),
+        });
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(data: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        write_var_int(&mut frame, data.len() as i32);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn unframe(framed: &[u8], threshold: usize) -> Vec<u8> {
+        let (length, length_len) = read_var_int(framed).unwrap();
+        let rest = &framed[length_len..];
+        assert_eq!(rest.len(), length as usize);
+
+        let (data_length, data_length_len) = read_var_int(rest).unwrap();
+        let payload = &rest[data_length_len..];
+        if data_length == 0 {
+            payload.to_vec()
+        } else {
+            decompress(payload, data_length as usize, threshold).unwrap()
+        }
+    }
+
+    #[test]
+    fn small_packet_uncompressed() {
+        let data = [0x21, 1, 2, 3];
+        let framed = compress_frame(&frame(&data), 256).unwrap();
+        assert_eq!(framed, vec![5, 0, 0x21, 1, 2, 3]);
+    }
+
+    #[test]
+    fn large_packet_round_trip() {
+        let data: Vec<u8> = (0..5000).map(|i| (i % 7) as u8).collect();
+        let framed = compress_frame(&frame(&data), 256).unwrap();
+        assert!(framed.len() < data.len());
+        assert_eq!(unframe(&framed, 256), data);
+    }
+
+    #[test]
+    fn bad_data_length() {
+        let data = vec![0u8; 1000];
+        let framed = compress_frame(&frame(&data), 256).unwrap();
+        let payload = &framed[read_var_int(&framed).unwrap().1 + 2..];
+
+        // lies about length
+        assert!(decompress(payload, 999, 256).is_err());
+        assert!(decompress(payload, 1001, 256).is_err());
+
+        // under the threshold should never have been compressed
+        assert!(decompress(payload, 1000, 1024).is_err());
+    }
+}
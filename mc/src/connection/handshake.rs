@@ -5,7 +5,7 @@ use minecraft_server_protocol::types::*;
 impl HandshakeState {
     pub async fn handle_transaction(self, packet: PacketBody) -> McResult<ActiveState> {
         use crate::packet::handshaking::server::*;
-        // TODO support legacy ping
+        // legacy pings are handled by ActiveComms before getting this far
         let handshake = SetProtocol::read_packet(packet).await?;
 
         match handshake.next_state.value() {
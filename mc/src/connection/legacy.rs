@@ -0,0 +1,73 @@
+use crate::server::ServerData;
+
+/// First byte of every pre-1.7 server list ping
+pub const LEGACY_PING: u8 = 0xfe;
+
+/// Kick packet ID the legacy response is disguised as
+const LEGACY_KICK: u8 = 0xff;
+
+/// Protocol version advertised to legacy clients, which is never compatible so they show the
+/// server version instead
+const LEGACY_PROTOCOL_VERSION: i32 = 127;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LegacyPing {
+    /// Beta 1.8 to 1.3, sends just 0xFE
+    Beta,
+
+    /// 1.4 to 1.6, sends 0xFE 0x01, with 1.6 following up with a plugin message
+    Release,
+}
+
+impl LegacyPing {
+    /// The kick string with the server info in the format this client understands
+    pub async fn response(self, server_data: &ServerData) -> String {
+        let config = server_data.config();
+        let online = server_data.player_list().count().await;
+
+        match self {
+            LegacyPing::Beta => format!(
+                "{}§{}§{}",
+                // can't contain the delimiter
+                config.motd.replace('§', ""),
+                online,
+                config.max_players
+            ),
+            LegacyPing::Release => format!(
+                "§1\0{}\0{}\0{}\0{}\0{}",
+                LEGACY_PROTOCOL_VERSION,
+                crate::packet::GAME_VERSION,
+                config.motd,
+                online,
+                config.max_players
+            ),
+        }
+    }
+}
+
+/// Encodes as a legacy kick packet, a UTF-16BE string prefixed with its length in code units
+pub fn encode_response(response: &str) -> Vec<u8> {
+    let units: Vec<u16> = response.encode_utf16().collect();
+
+    let mut buf = Vec::with_capacity(3 + units.len() * 2);
+    buf.push(LEGACY_KICK);
+    buf.extend_from_slice(&(units.len() as u16).to_be_bytes());
+    for unit in units {
+        buf.extend_from_slice(&unit.to_be_bytes());
+    }
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        assert_eq!(
+            encode_response("§1\0hi"),
+            vec![0xff, 0x00, 0x05, 0x00, 0xa7, 0x00, 0x31, 0x00, 0x00, 0x00, 0x68, 0x00, 0x69]
+        );
+    }
+}
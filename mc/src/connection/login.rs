@@ -1,10 +1,13 @@
 use std::mem;
+use std::net::IpAddr;
 
+use openssl::hash::MessageDigest;
 use uuid::adapter::HyphenatedRef;
 use uuid::Uuid;
 
 use crate::connection::comms::{CommsRef, ResponseSink};
 use crate::connection::{ActiveState, LoginState, PlayState};
+use crate::game::{ConnectionId, ProfileProperty};
 use crate::packet::login::{client, server::*};
 use crate::packet::DisconnectExt;
 use crate::prelude::*;
@@ -17,6 +20,19 @@ fn generate_verify_token() -> McResult<Vec<u8>> {
     Ok(token)
 }
 
+/// Same UUID as vanilla gives the player in offline mode, so it's stable across logins
+pub(crate) fn offline_uuid(player_name: &str) -> McResult<Uuid> {
+    let name = format!("OfflinePlayer:{}", player_name);
+    let digest = openssl::hash::hash(MessageDigest::md5(), name.as_bytes())?;
+
+    // version 3 (name based, MD5) with the RFC 4122 variant
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest);
+    bytes[6] = (bytes[6] & 0x0f) | 0x30;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    Ok(Uuid::from_bytes(bytes))
+}
+
 const SERVER_ID: &str = "";
 
 impl LoginState {
@@ -24,6 +40,7 @@ impl LoginState {
         mut self,
         packet: PacketBody,
         server_data: &ServerData,
+        peer: IpAddr,
         comms: &mut CommsRef<R>,
     ) -> McResult<ActiveState> {
         let result = async {
@@ -36,11 +53,18 @@ impl LoginState {
                     match server_data.online_status()? {
                         OnlineStatus::Offline => {
                             // no auth
-                            let player_uuid = Uuid::new_v4();
-                            let (login_success, play_state) =
-                                self.into_play_state(player_name, player_uuid)?;
+                            let player_uuid = offline_uuid(&player_name)?;
+                            let play_state = self
+                                .into_play_state(
+                                    player_name,
+                                    player_uuid,
+                                    Vec::new(),
+                                    server_data,
+                                    peer,
+                                    comms,
+                                )
+                                .await?;
 
-                            comms.send_response(login_success).await?;
                             Ok(ActiveState::Play(play_state))
                         }
                         OnlineStatus::Online { public_key } => {
@@ -87,24 +111,33 @@ impl LoginState {
 
                         let player_name = mem::take(&mut self.player_name);
                         debug!("enabling AES packet encryption for player {}", player_name);
-                        comms.upgrade(decrypted_shared_secret.clone()).await;
+                        comms.upgrade(decrypted_shared_secret.clone())?;
 
                         debug!("authenticating player with mojang");
                         let auth_response = auth::auth(
+                            &server_data.config().session_server,
                             &player_name,
                             SERVER_ID,
                             &decrypted_shared_secret,
                             &public_key,
-                        )?;
+                        )
+                        .await?;
                         debug!(
                             "authenticated player with mojang, got uuid of {}",
                             auth_response.uuid
                         );
 
-                        let (login_success, play_state) =
-                            self.into_play_state(player_name, auth_response.uuid)?;
+                        let play_state = self
+                            .into_play_state(
+                                player_name,
+                                auth_response.uuid,
+                                auth_response.properties,
+                                server_data,
+                                peer,
+                                comms,
+                            )
+                            .await?;
 
-                        comms.send_response(login_success).await?;
                         Ok(ActiveState::Play(play_state))
                     }
                 }
@@ -125,11 +158,26 @@ impl LoginState {
 }
 
 impl LoginState {
-    fn into_play_state(
+    /// Checks the player is allowed to join, then enables compression if configured and sends
+    /// login success
+    async fn into_play_state<R: ResponseSink>(
         self,
         player_name: String,
         player_uuid: Uuid,
-    ) -> McResult<(client::Success, PlayState)> {
+        properties: Vec<ProfileProperty>,
+        server_data: &ServerData,
+        peer: IpAddr,
+        comms: &mut CommsRef<R>,
+    ) -> McResult<PlayState> {
+        server_data
+            .access()
+            .check_login(&player_uuid, &player_name, peer)
+            .await?;
+
+        if let Some(threshold) = server_data.config().compression_threshold {
+            comms.enable_compression(threshold).await?;
+        }
+
         let encoded_uuid = {
             let mut buf = vec![0u8; HyphenatedRef::LENGTH];
             player_uuid.to_hyphenated_ref().encode_upper(&mut buf);
@@ -143,28 +191,33 @@ impl LoginState {
             username: StringField::new(player_name.clone()),
         };
 
-        let state = PlayState {
+        comms.send_response(response).await?;
+
+        Ok(PlayState {
             player_name,
             uuid: player_uuid.into(),
-        };
-
-        Ok((response, state))
+            connection: ConnectionId::next(),
+            properties,
+        })
     }
 }
 
 mod auth {
     use std::str::FromStr;
 
+    use async_std::task;
     use log::*;
     use num::BigInt;
     use openssl::sha::Sha1;
     use uuid::Uuid;
 
     use crate::error::{McError, McResult};
+    use crate::game::ProfileProperty;
 
     pub struct AuthResponse {
         pub uuid: Uuid,
-        // TODO skin
+        /// Signed skin textures
+        pub properties: Vec<ProfileProperty>,
     }
 
     fn generate_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
@@ -182,51 +235,83 @@ mod auth {
         big_int.to_str_radix(16)
     }
 
-    pub fn auth(
+    /// Asks the session server whether the player has joined with this shared secret. The
+    /// request blocks, so runs on the blocking pool rather than stalling the caller's executor
+    pub async fn auth(
+        session_server: &str,
         player_name: &str,
         server_id: &str,
         shared_secret: &[u8],
         public_key: &[u8],
     ) -> McResult<AuthResponse> {
         let hash = generate_hash(server_id, shared_secret, public_key);
+        let url = format!(
+            "{}/session/minecraft/hasJoined",
+            session_server.trim_end_matches('/')
+        );
+        let player_name = player_name.to_owned();
 
-        let response = ureq::get("https://sessionserver.mojang.com/session/minecraft/hasJoined")
+        task::spawn_blocking(move || has_joined(&url, &player_name, &hash)).await
+    }
+
+    fn has_joined(url: &str, player_name: &str, hash: &str) -> McResult<AuthResponse> {
+        let response = ureq::get(url)
             .query("username", player_name)
-            .query("serverId", &hash)
+            .query("serverId", hash)
             .timeout_connect(10_000)
             .timeout_read(10_000)
             .call();
 
-        if response.ok() {
-            let json = match response.into_json().map_err(McError::Auth)? {
-                ureq::SerdeValue::Object(obj) => obj,
-                _ => return Err(McError::BadAuthResponse),
-            };
+        if let Some(err) = response.synthetic_error() {
+            return Err(McError::SessionServerUnavailable(err.to_string()));
+        }
 
-            match (json.get("id"), json.get("name")) {
-                (Some(ureq::SerdeValue::String(uuid)), Some(ureq::SerdeValue::String(name))) => {
-                    if name != player_name {
-                        warn!("incorrect player name returned from mojang, expected '{}' but got '{}'", player_name, name);
-                        return Err(McError::BadAuthResponse);
-                    }
+        match response.status() {
+            200 => {}
+            // the client didn't join with this secret
+            204 => return Err(McError::NotAuthenticated),
+            status => return Err(McError::UnexpectedAuthResponse(status)),
+        }
 
-                    let uuid = match Uuid::from_str(uuid) {
-                        Ok(uuid) => uuid,
-                        Err(_) => {
-                            warn!("bad uuid returned from mojang ('{}')", uuid);
-                            return Err(McError::BadAuthResponse);
-                        }
-                    };
+        let json = match response.into_json().map_err(McError::Auth)? {
+            ureq::SerdeValue::Object(obj) => obj,
+            _ => return Err(McError::BadAuthResponse),
+        };
 
-                    Ok(AuthResponse { uuid })
-                }
-                _ => {
-                    warn!("bad json returned from mojang");
-                    Err(McError::BadAuthResponse)
+        match (json.get("id"), json.get("name")) {
+            (Some(ureq::SerdeValue::String(uuid)), Some(ureq::SerdeValue::String(name))) => {
+                if name != player_name {
+                    warn!(
+                        "incorrect player name returned from mojang, expected '{}' but got '{}'",
+                        player_name, name
+                    );
+                    return Err(McError::BadAuthResponse);
                 }
+
+                let uuid = match Uuid::from_str(uuid) {
+                    Ok(uuid) => uuid,
+                    Err(_) => {
+                        warn!("bad uuid returned from mojang ('{}')", uuid);
+                        return Err(McError::BadAuthResponse);
+                    }
+                };
+
+                let properties = match json.get("properties") {
+                    Some(properties) => {
+                        serde_json::from_value(properties.clone()).map_err(|e| {
+                            warn!("bad profile properties returned from mojang: {}", e);
+                            McError::BadAuthResponse
+                        })?
+                    }
+                    None => Vec::new(),
+                };
+
+                Ok(AuthResponse { uuid, properties })
+            }
+            _ => {
+                warn!("bad json returned from mojang");
+                Err(McError::BadAuthResponse)
             }
-        } else {
-            Err(McError::UnexpectedAuthResponse(response.status()))
         }
     }
 
@@ -239,7 +324,11 @@ mod auth {
 
     #[cfg(test)]
     mod tests {
-        use super::generate_hash_from_name;
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+        use std::thread;
+
+        use super::*;
 
         #[test]
         fn hash() {
@@ -256,5 +345,116 @@ mod auth {
                 generate_hash_from_name("simon")
             );
         }
+
+        /// Stand-in session server that answers a single request, returning its base URL and
+        /// the request line it received
+        fn session_server(response: &'static str) -> (String, thread::JoinHandle<String>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+
+            let handle = thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+
+                // skip headers
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                request_line
+            });
+
+            (url, handle)
+        }
+
+        fn auth_as(url: &str, name: &str) -> McResult<AuthResponse> {
+            task::block_on(auth(url, name, "", b"secret", b"key"))
+        }
+
+        #[test]
+        fn authenticated() {
+            let (url, server) = session_server(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 125\r\n\r\n\
+                 {\"id\":\"069a79f444e94726a5befca90e38aaf5\",\"name\":\"Notch\",\"properties\":\
+                 [{\"name\":\"textures\",\"value\":\"e30=\",\"signature\":\"c2ln\"}]}",
+            );
+
+            let response = auth_as(&url, "Notch").unwrap();
+            assert_eq!(
+                response.uuid,
+                Uuid::from_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap()
+            );
+            assert_eq!(
+                response.properties,
+                vec![ProfileProperty {
+                    name: "textures".to_owned(),
+                    value: "e30=".to_owned(),
+                    signature: Some("c2ln".to_owned()),
+                }]
+            );
+
+            let request = server.join().unwrap();
+            assert!(request.starts_with("GET /session/minecraft/hasJoined?"));
+            assert!(request.contains("username=Notch"));
+        }
+
+        #[test]
+        fn not_authenticated() {
+            let (url, _) = session_server("HTTP/1.1 204 No Content\r\n\r\n");
+            assert!(matches!(
+                auth_as(&url, "Notch"),
+                Err(McError::NotAuthenticated)
+            ));
+
+            // someone else's profile
+            let (url, _) = session_server(
+                "HTTP/1.1 200 OK\r\nContent-Length: 55\r\n\r\n\
+                 {\"id\":\"853c80ef3c3749fdaa49938b674adae6\",\"name\":\"jeb_\"}",
+            );
+            assert!(matches!(
+                auth_as(&url, "Notch"),
+                Err(McError::BadAuthResponse)
+            ));
+        }
+
+        #[test]
+        fn server_error() {
+            let (url, _) =
+                session_server("HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n");
+            assert!(matches!(
+                auth_as(&url, "Notch"),
+                Err(McError::UnexpectedAuthResponse(503))
+            ));
+
+            // nothing listening
+            let url = {
+                let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                format!("http://{}", listener.local_addr().unwrap())
+            };
+            assert!(matches!(
+                auth_as(&url, "Notch"),
+                Err(McError::SessionServerUnavailable(_))
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offline_uuids() {
+        for (name, expected) in &[
+            ("Notch", "b50ad385-829d-3141-a216-7e7d7539ba7f"),
+            ("jeb_", "a762f560-4fce-3236-812a-b80efff0b62b"),
+        ] {
+            let uuid = offline_uuid(name).unwrap();
+            assert_eq!(uuid.to_hyphenated().to_string(), *expected);
+        }
     }
 }
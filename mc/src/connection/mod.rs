@@ -1,16 +1,21 @@
 pub use comms::{ActiveComms, ClientBoundPacket, CommsRef};
+pub use legacy::LegacyPing;
+pub(crate) use login::offline_uuid;
 
 use crate::connection::comms::ResponseSink;
 
-use crate::game::{ClientMessage, ClientMessageSender, ClientUuid};
+use crate::game::{ClientMessage, ClientMessageSender, ClientUuid, ConnectionId, ProfileProperty};
 use crate::packet::DisconnectExt;
 use crate::prelude::*;
 use crate::server::ServerData;
 use futures::SinkExt;
 use minecraft_server_protocol::types::PacketBody;
+use std::net::IpAddr;
 
 mod comms;
+mod compression;
 mod handshake;
+mod legacy;
 mod login;
 mod play;
 mod status;
@@ -38,6 +43,9 @@ struct LoginState {
 struct PlayState {
     pub player_name: String,
     pub uuid: ClientUuid,
+    pub connection: ConnectionId,
+    /// Empty in offline mode
+    pub properties: Vec<ProfileProperty>,
 }
 
 enum ActiveState {
@@ -57,8 +65,11 @@ pub struct ConnectionState<R: ResponseSink> {
     state: ActiveState,
     comms: CommsRef<R>, // TODO rename
 
+    /// Address of the client, checked against IP bans
+    peer: IpAddr,
+
     /// Once assigned a UUID in PlayState, don't forget it for disconnection
-    play_uuid: Option<ClientUuid>,
+    play_uuid: Option<(ClientUuid, ConnectionId)>,
 }
 
 pub enum PostPacketAction {
@@ -66,6 +77,8 @@ pub enum PostPacketAction {
     EnteredPlayState {
         player_name: String,
         player_uuid: ClientUuid,
+        connection: ConnectionId,
+        properties: Vec<ProfileProperty>,
     },
 }
 
@@ -76,10 +89,11 @@ impl Default for PostPacketAction {
 }
 
 impl<R: ResponseSink> ConnectionState<R> {
-    pub fn new(comms: CommsRef<R>) -> Self {
+    pub fn new(comms: CommsRef<R>, peer: IpAddr) -> Self {
         Self {
             state: ActiveState::default(),
             comms,
+            peer,
             play_uuid: None,
         }
     }
@@ -96,19 +110,25 @@ impl<R: ResponseSink> ConnectionState<R> {
         let mut action = PostPacketAction::default();
         let result = match state {
             ActiveState::Handshake(state) => state.handle_transaction(packet).await,
-            ActiveState::Status(state) => state.handle_transaction(packet, &mut self.comms).await,
+            ActiveState::Status(state) => {
+                state
+                    .handle_transaction(packet, server_data, &mut self.comms)
+                    .await
+            }
             ActiveState::Login(state) => {
                 is_login = true;
 
                 let result = state
-                    .handle_transaction(packet, server_data, &mut self.comms)
+                    .handle_transaction(packet, server_data, self.peer, &mut self.comms)
                     .await;
 
                 if let Ok(ActiveState::Play(play)) = &result {
-                    self.play_uuid = Some(play.uuid);
+                    self.play_uuid = Some((play.uuid, play.connection));
                     action = PostPacketAction::EnteredPlayState {
                         player_name: play.player_name.clone(),
                         player_uuid: play.uuid,
+                        connection: play.connection,
+                        properties: play.properties.clone(),
                     };
                 }
 
@@ -146,10 +166,10 @@ impl<R: ResponseSink> ConnectionState<R> {
     }
 
     pub async fn on_disconnect(&mut self, game_broker: &mut ClientMessageSender) -> McResult<()> {
-        if let Some(uuid) = self.play_uuid.take() {
+        if let Some((uuid, connection)) = self.play_uuid.take() {
             // game must be notified if the player has joined
             game_broker
-                .send((uuid, ClientMessage::PlayerDisconnected))
+                .send((uuid, connection, ClientMessage::PlayerDisconnected))
                 .await?;
         }
 
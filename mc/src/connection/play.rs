@@ -7,9 +7,6 @@ use crate::game::{ClientMessage, ClientMessageSender};
 use crate::prelude::*;
 use minecraft_server_protocol::types::IdentifierField;
 
-// TODO Keep Alive
-// TODO Join Game
-// TODO Chunk Data (nbt heightmaps, optional fields in packet format, some actual chunk data)
 // TODO central server instance with player list,world etc, and functionality like kick()
 
 impl PlayState {
@@ -21,8 +18,14 @@ impl PlayState {
         use crate::packet::play::{client, server::*};
         match packet.id {
             Settings::ID => {
-                let _client_settings = Settings::read_packet(packet).await?;
-                // whatever
+                let settings = Settings::read_packet(packet).await?;
+                game_broker
+                    .send((
+                        self.uuid,
+                        self.connection,
+                        ClientMessage::ViewDistance(i32::from(*settings.view_distance.value())),
+                    ))
+                    .await?;
                 Ok(())
             }
 
@@ -53,23 +56,85 @@ impl PlayState {
                 game_broker
                     .send((
                         self.uuid,
+                        self.connection,
                         ClientMessage::VerifyTeleport(confirmation.teleport_id.value()),
                     ))
                     .await?;
                 Ok(())
             }
 
-            PositionLook::ID => {
-                let _pos = PositionLook::read_packet(packet).await?;
-                // TODO actually use
+            Chat::ID => {
+                let chat = Chat::read_packet(packet).await?;
+                game_broker
+                    .send((
+                        self.uuid,
+                        self.connection,
+                        ClientMessage::Chat(chat.message.take()),
+                    ))
+                    .await?;
                 Ok(())
             }
 
+            TabComplete::ID => {
+                let request = TabComplete::read_packet(packet).await?;
+                game_broker
+                    .send((
+                        self.uuid,
+                        self.connection,
+                        ClientMessage::TabComplete {
+                            transaction_id: request.transaction_id.value(),
+                            text: request.text.take(),
+                        },
+                    ))
+                    .await?;
+                Ok(())
+            }
+
+            PositionLook::ID => {
+                let pos = PositionLook::read_packet(packet).await?;
+                self.send_movement(
+                    game_broker,
+                    Some((*pos.x.value(), *pos.y.value(), *pos.z.value())),
+                    Some((*pos.yaw.value(), *pos.pitch.value())),
+                    *pos.on_ground.value(),
+                )
+                .await
+            }
+
+            Position::ID => {
+                let pos = Position::read_packet(packet).await?;
+                self.send_movement(
+                    game_broker,
+                    Some((*pos.x.value(), *pos.y.value(), *pos.z.value())),
+                    None,
+                    *pos.on_ground.value(),
+                )
+                .await
+            }
+
+            Look::ID => {
+                let look = Look::read_packet(packet).await?;
+                self.send_movement(
+                    game_broker,
+                    None,
+                    Some((*look.yaw.value(), *look.pitch.value())),
+                    *look.on_ground.value(),
+                )
+                .await
+            }
+
+            Flying::ID => {
+                let flying = Flying::read_packet(packet).await?;
+                self.send_movement(game_broker, None, None, *flying.on_ground.value())
+                    .await
+            }
+
             KeepAlive::ID => {
                 let keep_alive = KeepAlive::read_packet(packet).await?;
                 game_broker
                     .send((
                         self.uuid,
+                        self.connection,
                         ClientMessage::VerifyKeepAlive(*keep_alive.keep_alive_id.value()),
                     ))
                     .await?;
@@ -82,4 +147,25 @@ impl PlayState {
 
         Ok(ActiveState::Play(self))
     }
+
+    async fn send_movement(
+        &self,
+        game_broker: &mut ClientMessageSender,
+        position: Option<(f64, f64, f64)>,
+        rotation: Option<(f32, f32)>,
+        on_ground: bool,
+    ) -> McResult<()> {
+        game_broker
+            .send((
+                self.uuid,
+                self.connection,
+                ClientMessage::Movement {
+                    position,
+                    rotation,
+                    on_ground,
+                },
+            ))
+            .await?;
+        Ok(())
+    }
 }
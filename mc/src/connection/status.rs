@@ -1,13 +1,42 @@
+use serde::Serialize;
+use serde_json::json;
+
 use crate::connection::comms::{CommsRef, ResponseSink};
 use crate::connection::{ActiveState, StatusState};
 use crate::packet::*;
 use crate::prelude::*;
+use crate::server::ServerData;
 use minecraft_server_protocol::types::*;
 
+/// Max number of players listed in the server list hover
+const PLAYER_SAMPLE_SIZE: usize = 12;
+
+#[derive(Serialize)]
+struct StatusResponse<'a> {
+    version: serde_json::Value,
+    players: StatusPlayers,
+    description: serde_json::Value,
+    favicon: &'a str,
+}
+
+#[derive(Serialize)]
+struct StatusPlayers {
+    max: u32,
+    online: usize,
+    sample: Vec<SamplePlayer>,
+}
+
+#[derive(Serialize)]
+struct SamplePlayer {
+    name: String,
+    id: String,
+}
+
 impl StatusState {
     pub async fn handle_transaction<R: ResponseSink>(
         self,
         packet: PacketBody,
+        server_data: &ServerData,
         comms: &mut CommsRef<R>,
     ) -> McResult<ActiveState> {
         use crate::packet::status::{client, server::*};
@@ -16,10 +45,7 @@ impl StatusState {
                 let _empty = PingStart::read_packet(packet).await?;
 
                 let status = client::ServerInfo {
-                    response: StringField::new(generate_json(
-                        "mInEcRaFt",
-                        include_str!("../../icon.png.base64"),
-                    )),
+                    response: StringField::new(generate_json(server_data).await?),
                 };
 
                 comms.send_response(status).await?;
@@ -38,14 +64,30 @@ impl StatusState {
     }
 }
 
-fn generate_json(description: &str, icon_b64: &str) -> String {
-    format!(
-        "{{\"version\": {{\"name\": \"{game_version}\", \"protocol\": {protocol_version} }},\
-    \"players\": {{ \"max\": 10, \"online\": 7, \"sample\": [] }}, \"description\": {{ \"text\": \"{desc}\" }},\
-    \"favicon\": \"data:image/png;base64,{icon}\"}}",
-        game_version = GAME_VERSION,
-        protocol_version = PROTOCOL_VERSION,
-        desc = description,
-        icon = icon_b64
-    )
+async fn generate_json(server_data: &ServerData) -> McResult<String> {
+    let config = server_data.config();
+    let players = server_data.player_list();
+
+    let sample = players
+        .sample(PLAYER_SAMPLE_SIZE)
+        .await
+        .into_iter()
+        .map(|(uuid, name)| SamplePlayer {
+            name: name.to_string(),
+            id: uuid.to_string(),
+        })
+        .collect();
+
+    let response = StatusResponse {
+        version: json!({ "name": GAME_VERSION, "protocol": PROTOCOL_VERSION }),
+        players: StatusPlayers {
+            max: config.max_players,
+            online: players.count().await,
+            sample,
+        },
+        description: json!({ "text": config.motd }),
+        favicon: server_data.favicon(),
+    };
+
+    serde_json::to_string(&response).map_err(McError::Json)
 }
@@ -1,4 +1,5 @@
 use std::io;
+use std::path::PathBuf;
 use thiserror::*;
 
 use futures::channel::mpsc::SendError;
@@ -18,12 +19,21 @@ pub enum McError {
     #[error(transparent)]
     Packet(#[from] minecraft_server_protocol::types::PacketError),
 
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
     #[error("Unknown sink error")]
     SinkUnknown,
 
     #[error("Malformed packet with length {0}")]
     MalformedPacket(usize),
 
+    #[error("Compressed packet claimed to be {expected} bytes but was {actual}")]
+    BadDecompressedLength { expected: usize, actual: usize },
+
+    #[error("Malformed legacy ping, unexpected byte {0:#04x}")]
+    BadLegacyPing(u8),
+
     #[error("Unexpected packet with ID {0:#04x}")]
     BadPacketId(i32),
 
@@ -45,15 +55,64 @@ pub enum McError {
     #[error("Unexpected {0} response from Mojang during authentication")]
     UnexpectedAuthResponse(u16),
 
+    #[error("Failed to verify username")]
+    NotAuthenticated,
+
+    #[error("Couldn't reach the session server: {0}")]
+    SessionServerUnavailable(String),
+
     #[error("No such player with UUID {0:?}")]
     NoSuchPlayer(ClientUuid),
 
     #[error("Incorrect teleport confirmation, expected {expected:?} but got {actual}")]
     IncorrectTeleportConfirm { expected: Option<i32>, actual: i32 },
 
-    #[error("Incorrect keep-alive response, expected 4 but got {0}")]
+    #[error("Illegal characters in chat")]
+    IllegalChat,
+
+    #[error("Chat message is {0} characters long, the maximum is 256")]
+    ChatTooLong(usize),
+
+    #[error("Invalid movement to {0:?}")]
+    BadMovement((f64, f64, f64)),
+
+    #[error("Unexpected keep-alive response {0}")]
     IncorrectKeepAlive(i64),
 
+    #[error("Invalid value for '{key}' in server config: {reason}")]
+    BadConfig { key: String, reason: String },
+
+    #[error("Invalid server icon: {0}")]
+    BadIcon(String),
+
+    #[error("Invalid NBT: {0}")]
+    BadNbt(String),
+
+    #[error("Invalid region file: {0}")]
+    BadRegion(String),
+
+    #[error("World storage is unusable after a panic")]
+    StoragePanicked,
+
+    #[error("Invalid chunk data: {0}")]
+    BadChunk(String),
+
+    #[error("Invalid level.dat: {0}")]
+    BadLevel(String),
+
+    #[error("Invalid block report: {0}")]
+    BadBlockReport(String),
+
+    #[error("Invalid world generator settings: {0}")]
+    BadGeneratorSettings(String),
+
+    #[error("Invalid {path:?}: {reason}")]
+    BadAccessList { path: PathBuf, reason: String },
+
+    /// Banned or not whitelisted, with the message to show the player
+    #[error("{0}")]
+    LoginDenied(String),
+
     #[error("Invalid next state {0}")]
     BadNextState(i32),
 }
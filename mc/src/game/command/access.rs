@@ -0,0 +1,327 @@
+//! Commands managing the whitelist and bans
+
+use std::net::IpAddr;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+
+use crate::access::{Ban, IpBan, PlayerBan, WhitelistEntry};
+use crate::game::command::{
+    ArgumentType, Command, CommandContext, CommandError, CommandRegistry, CommandResult, StringKind,
+};
+use crate::game::Game;
+
+const WORD: ArgumentType = ArgumentType::String(StringKind::Word);
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(
+        Command::new("ban", ban)
+            .permission(3)
+            .arg("target", ArgumentType::Player)
+            .optional_arg("reason", ArgumentType::Message),
+    );
+    registry.register(
+        Command::new("ban-ip", ban_ip)
+            .permission(3)
+            .arg("target", WORD)
+            .optional_arg("reason", ArgumentType::Message),
+    );
+    registry.register(
+        Command::new("pardon", pardon)
+            .permission(3)
+            .arg("target", WORD),
+    );
+    registry.register(
+        Command::new("pardon-ip", pardon_ip)
+            .permission(3)
+            .arg("target", WORD),
+    );
+    registry.register(Command::new("banlist", banlist_players).permission(3));
+    registry.register(
+        Command::new("banlist", banlist_players)
+            .permission(3)
+            .literal("players"),
+    );
+    registry.register(
+        Command::new("banlist", banlist_ips)
+            .permission(3)
+            .literal("ips"),
+    );
+
+    registry.register(
+        Command::new("whitelist", whitelist_on)
+            .permission(3)
+            .literal("on"),
+    );
+    registry.register(
+        Command::new("whitelist", whitelist_off)
+            .permission(3)
+            .literal("off"),
+    );
+    registry.register(
+        Command::new("whitelist", whitelist_add)
+            .permission(3)
+            .literal("add")
+            .arg("target", ArgumentType::Player),
+    );
+    registry.register(
+        Command::new("whitelist", whitelist_remove)
+            .permission(3)
+            .literal("remove")
+            .arg("target", WORD),
+    );
+    registry.register(
+        Command::new("whitelist", whitelist_list)
+            .permission(3)
+            .literal("list"),
+    );
+    registry.register(
+        Command::new("whitelist", whitelist_reload)
+            .permission(3)
+            .literal("reload"),
+    );
+}
+
+fn ban(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let source = game.sender_name(ctx.sender)?;
+        let (uuid, name) = game
+            .profile_by_name(ctx.string("target"))
+            .ok_or_else(unknown_profile)?;
+        let ban = Ban::new(&source, ctx.get("reason").and_then(|arg| arg.as_str()));
+        let message = format!("Banned {}: {}", name, ban.reason);
+
+        let access = game.access.clone();
+        if !access.ban_player(PlayerBan { uuid, name, ban }).await? {
+            return Err(CommandError::Failed(
+                "Nothing changed. The player is already banned".to_owned(),
+            ));
+        }
+
+        if let Some(client) = game.client_by_name_mut(ctx.string("target")) {
+            client.kick("You are banned from this server.").await?;
+        }
+
+        game.send_feedback(ctx.sender, &message).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn ban_ip(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let source = game.sender_name(ctx.sender)?;
+        let target = ctx.string("target");
+        let ip = match target.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => game
+                .client_by_name_mut(target)
+                .map(|c| c.address)
+                .ok_or_else(|| {
+                    CommandError::Failed("Invalid IP address or unknown player".to_owned())
+                })?,
+        };
+        let ban = Ban::new(&source, ctx.get("reason").and_then(|arg| arg.as_str()));
+        let message = format!("Banned IP {}: {}", ip, ban.reason);
+
+        let access = game.access.clone();
+        if !access.ban_ip(IpBan { ip, ban }).await? {
+            return Err(CommandError::Failed(
+                "Nothing changed. That IP is already banned".to_owned(),
+            ));
+        }
+
+        for client in game.clients_by_address_mut(ip) {
+            client.kick("You have been IP banned.").await?;
+        }
+
+        game.send_feedback(ctx.sender, &message).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn pardon(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let name = ctx.string("target");
+        if !game.access.pardon_player(name).await? {
+            return Err(CommandError::Failed(
+                "Nothing changed. The player isn't banned".to_owned(),
+            ));
+        }
+
+        let message = format!("Unbanned {}", name);
+        game.send_feedback(ctx.sender, &message).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn pardon_ip(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let ip: IpAddr = ctx
+            .string("target")
+            .parse()
+            .map_err(|_| CommandError::Failed("Invalid IP address".to_owned()))?;
+        if !game.access.pardon_ip(ip).await? {
+            return Err(CommandError::Failed(
+                "Nothing changed. That IP isn't banned".to_owned(),
+            ));
+        }
+
+        let message = format!("Unbanned IP {}", ip);
+        game.send_feedback(ctx.sender, &message).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn banlist_players(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let bans: Vec<_> = game
+            .access
+            .banned_players()
+            .await
+            .into_iter()
+            .map(|b| (b.name, b.ban))
+            .collect();
+        send_bans(game, ctx, bans).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn banlist_ips(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let bans: Vec<_> = game
+            .access
+            .banned_ips()
+            .await
+            .into_iter()
+            .map(|b| (b.ip.to_string(), b.ban))
+            .collect();
+        send_bans(game, ctx, bans).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+async fn send_bans(game: &mut Game, ctx: CommandContext, bans: Vec<(String, Ban)>) {
+    if bans.is_empty() {
+        game.send_feedback(ctx.sender, "There are no bans").await;
+        return;
+    }
+
+    let header = format!("There are {} ban(s):", bans.len());
+    game.send_feedback(ctx.sender, &header).await;
+    for (target, ban) in bans {
+        let line = format!("{} was banned by {}: {}", target, ban.source, ban.reason);
+        game.send_feedback(ctx.sender, &line).await;
+    }
+}
+
+fn whitelist_on(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    set_whitelist_enabled(game, ctx, true).boxed()
+}
+
+fn whitelist_off(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    set_whitelist_enabled(game, ctx, false).boxed()
+}
+
+async fn set_whitelist_enabled(
+    game: &mut Game,
+    ctx: CommandContext,
+    enabled: bool,
+) -> CommandResult {
+    let state = if enabled { "on" } else { "off" };
+    if game.access.whitelist_enabled().await == enabled {
+        return Err(CommandError::Failed(format!(
+            "Whitelist is already turned {}",
+            state
+        )));
+    }
+
+    game.access.set_whitelist_enabled(enabled).await;
+    let message = format!("Whitelist is now turned {}", state);
+    game.send_feedback(ctx.sender, &message).await;
+    Ok(())
+}
+
+fn whitelist_add(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let (uuid, name) = game
+            .profile_by_name(ctx.string("target"))
+            .ok_or_else(unknown_profile)?;
+        let message = format!("Added {} to the whitelist", name);
+
+        if !game
+            .access
+            .whitelist_add(WhitelistEntry { uuid, name })
+            .await?
+        {
+            return Err(CommandError::Failed(
+                "Player is already whitelisted".to_owned(),
+            ));
+        }
+
+        game.send_feedback(ctx.sender, &message).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn whitelist_remove(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let name = ctx.string("target");
+        if !game.access.whitelist_remove(name).await? {
+            return Err(CommandError::Failed("Player is not whitelisted".to_owned()));
+        }
+
+        let message = format!("Removed {} from the whitelist", name);
+        game.send_feedback(ctx.sender, &message).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn whitelist_list(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let names: Vec<_> = game
+            .access
+            .whitelist()
+            .await
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+
+        let message = if names.is_empty() {
+            "There are no whitelisted players".to_owned()
+        } else {
+            format!(
+                "There are {} whitelisted players: {}",
+                names.len(),
+                names.join(", ")
+            )
+        };
+        game.send_feedback(ctx.sender, &message).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn whitelist_reload(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        game.access.reload().await?;
+        game.send_feedback(ctx.sender, "Reloaded the whitelist and bans")
+            .await;
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Names aren't looked up with Mojang, so in online mode only online players' UUIDs are known
+fn unknown_profile() -> CommandError {
+    CommandError::Failed(
+        "That player must be online to be looked up while the server is in online mode".to_owned(),
+    )
+}
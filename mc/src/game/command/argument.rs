@@ -0,0 +1,325 @@
+use crate::game::command::CommandError;
+use crate::packet::PacketBuilder;
+
+/// Argument parsers understood by the client, which it uses to validate and highlight input
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ArgumentType {
+    Bool,
+    Integer {
+        min: Option<i32>,
+        max: Option<i32>,
+    },
+    Double {
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    String(StringKind),
+    /// Name of an online player
+    Player,
+    /// Rest of the input, as typed
+    Message,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StringKind {
+    Word = 0,
+    /// Single word or a quoted phrase
+    Quotable = 1,
+    /// Rest of the input
+    Greedy = 2,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgumentValue {
+    Bool(bool),
+    Integer(i32),
+    Double(f64),
+    String(String),
+}
+
+/// Cursor over command input
+pub struct Reader<'a> {
+    input: &'a str,
+    cursor: usize,
+}
+
+impl ArgumentType {
+    pub fn integer() -> Self {
+        ArgumentType::Integer {
+            min: None,
+            max: None,
+        }
+    }
+
+    pub fn parse(self, reader: &mut Reader) -> Result<ArgumentValue, CommandError> {
+        let start = reader.cursor();
+        let syntax_error = |expected: &str| CommandError::Syntax {
+            message: format!("Expected {}", expected),
+            position: start,
+        };
+
+        let value = match self {
+            ArgumentType::Bool => match reader.read_word() {
+                "true" => ArgumentValue::Bool(true),
+                "false" => ArgumentValue::Bool(false),
+                _ => return Err(syntax_error("true or false")),
+            },
+            ArgumentType::Integer { min, max } => {
+                let value: i32 = reader
+                    .read_word()
+                    .parse()
+                    .map_err(|_| syntax_error("integer"))?;
+                check_range(value, min, max, start)?;
+                ArgumentValue::Integer(value)
+            }
+            ArgumentType::Double { min, max } => {
+                let value: f64 = reader
+                    .read_word()
+                    .parse()
+                    .map_err(|_| syntax_error("double"))?;
+                check_range(value, min, max, start)?;
+                ArgumentValue::Double(value)
+            }
+            ArgumentType::String(StringKind::Word) | ArgumentType::Player => {
+                ArgumentValue::String(reader.read_word().to_owned())
+            }
+            ArgumentType::String(StringKind::Quotable) => {
+                ArgumentValue::String(reader.read_quotable().ok_or_else(|| {
+                    CommandError::Syntax {
+                        message: "Unclosed quoted string".to_owned(),
+                        position: start,
+                    }
+                })?)
+            }
+            ArgumentType::String(StringKind::Greedy) | ArgumentType::Message => {
+                ArgumentValue::String(reader.read_rest().to_owned())
+            }
+        };
+
+        if reader.cursor() == start {
+            Err(syntax_error("value"))
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Completions for a partially typed argument
+    pub fn suggest(self, partial: &str, players: &[String]) -> Vec<String> {
+        let candidates: Vec<String> = match self {
+            ArgumentType::Bool => vec!["true".to_owned(), "false".to_owned()],
+            ArgumentType::Player => players.to_vec(),
+            _ => return Vec::new(),
+        };
+
+        candidates
+            .into_iter()
+            .filter(|s| starts_with_ignore_case(s, partial))
+            .collect()
+    }
+
+    /// Whether the client should ask the server for suggestions
+    pub fn asks_server(self) -> bool {
+        self == ArgumentType::Player
+    }
+
+    /// Parser identifier and properties in a DeclareCommands argument node
+    pub fn encode(self, packet: &mut PacketBuilder) {
+        match self {
+            ArgumentType::Bool => {
+                packet.string("brigadier:bool");
+            }
+            ArgumentType::Integer { min, max } => {
+                packet
+                    .string("brigadier:integer")
+                    .byte(range_flags(min.is_some(), max.is_some()));
+                if let Some(min) = min {
+                    packet.int(min);
+                }
+                if let Some(max) = max {
+                    packet.int(max);
+                }
+            }
+            ArgumentType::Double { min, max } => {
+                packet
+                    .string("brigadier:double")
+                    .byte(range_flags(min.is_some(), max.is_some()));
+                if let Some(min) = min {
+                    packet.double(min);
+                }
+                if let Some(max) = max {
+                    packet.double(max);
+                }
+            }
+            ArgumentType::String(kind) => {
+                packet.string("brigadier:string").var_int(kind as i32);
+            }
+            ArgumentType::Player => {
+                packet.string("minecraft:game_profile");
+            }
+            ArgumentType::Message => {
+                packet.string("minecraft:message");
+            }
+        }
+    }
+}
+
+impl ArgumentValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            ArgumentValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i32> {
+        match self {
+            ArgumentValue::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_double(&self) -> Option<f64> {
+        match self {
+            ArgumentValue::Double(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ArgumentValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self { input, cursor: 0 }
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn remaining(&self) -> &'a str {
+        &self.input[self.cursor..]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cursor == self.input.len()
+    }
+
+    /// Consumes the single space between arguments
+    pub fn expect_separator(&mut self) -> bool {
+        if self.remaining().starts_with(' ') {
+            self.cursor += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn read_word(&mut self) -> &'a str {
+        let remaining = self.remaining();
+        let len = remaining.find(' ').unwrap_or(remaining.len());
+        self.cursor += len;
+        &remaining[..len]
+    }
+
+    /// None if the closing quote is missing
+    pub fn read_quotable(&mut self) -> Option<String> {
+        let remaining = self.remaining();
+        if !remaining.starts_with('"') {
+            return Some(self.read_word().to_owned());
+        }
+
+        let mut out = String::new();
+        let mut escaped = false;
+        for (i, c) in remaining.char_indices().skip(1) {
+            match c {
+                _ if escaped => {
+                    out.push(c);
+                    escaped = false;
+                }
+                '\\' => escaped = true,
+                '"' => {
+                    self.cursor += i + 1;
+                    return Some(out);
+                }
+                c => out.push(c),
+            }
+        }
+
+        None
+    }
+
+    pub fn read_rest(&mut self) -> &'a str {
+        let rest = self.remaining();
+        self.cursor = self.input.len();
+        rest
+    }
+}
+
+pub fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    s.len() >= prefix.len()
+        && s.is_char_boundary(prefix.len())
+        && s[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn check_range<T: PartialOrd + std::fmt::Display>(
+    value: T,
+    min: Option<T>,
+    max: Option<T>,
+    position: usize,
+) -> Result<(), CommandError> {
+    let message = match (min, max) {
+        (Some(min), _) if value < min => format!("Must not be less than {}, found {}", min, value),
+        (_, Some(max)) if value > max => format!("Must not be more than {}, found {}", max, value),
+        _ => return Ok(()),
+    };
+
+    Err(CommandError::Syntax { message, position })
+}
+
+fn range_flags(min: bool, max: bool) -> i8 {
+    (min as i8) | (max as i8) << 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_arguments() {
+        let mut reader = Reader::new(r#"12 "quoted \"phrase\"" true the rest"#);
+        let int = ArgumentType::Integer {
+            min: Some(0),
+            max: Some(20),
+        };
+
+        assert_eq!(int.parse(&mut reader), Ok(ArgumentValue::Integer(12)));
+        assert!(reader.expect_separator());
+        assert_eq!(
+            ArgumentType::String(StringKind::Quotable).parse(&mut reader),
+            Ok(ArgumentValue::String(r#"quoted "phrase""#.to_owned()))
+        );
+        assert!(reader.expect_separator());
+        assert_eq!(
+            ArgumentType::Bool.parse(&mut reader),
+            Ok(ArgumentValue::Bool(true))
+        );
+        assert!(reader.expect_separator());
+        assert_eq!(
+            ArgumentType::Message.parse(&mut reader),
+            Ok(ArgumentValue::String("the rest".to_owned()))
+        );
+        assert!(reader.is_empty());
+
+        assert!(int.parse(&mut Reader::new("21")).is_err());
+        assert!(int.parse(&mut Reader::new("abc")).is_err());
+        assert!(ArgumentType::String(StringKind::Quotable)
+            .parse(&mut Reader::new("\"unclosed"))
+            .is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use futures::future::BoxFuture;
+use futures::FutureExt;
+
+use crate::access::OpEntry;
+use crate::chat::{self, ChatPosition};
+use crate::game::command::{
+    ArgumentType, Command, CommandContext, CommandError, CommandRegistry, CommandResult,
+    StringKind, MAX_PERMISSION_LEVEL,
+};
+use crate::game::Game;
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(
+        Command::new("help", help).optional_arg("command", ArgumentType::String(StringKind::Word)),
+    );
+    registry.register(
+        Command::new("kick", kick)
+            .permission(3)
+            .arg("target", ArgumentType::Player)
+            .optional_arg("reason", ArgumentType::Message),
+    );
+    registry.register(Command::new("list", list));
+    registry.register(Command::new("me", me).arg("action", ArgumentType::Message));
+    registry.register(
+        Command::new("msg", msg)
+            .alias("tell")
+            .alias("w")
+            .arg("target", ArgumentType::Player)
+            .arg("message", ArgumentType::Message),
+    );
+    registry.register(
+        Command::new("op", op)
+            .permission(3)
+            .arg("target", ArgumentType::Player),
+    );
+    registry.register(
+        Command::new("deop", deop)
+            .permission(3)
+            .arg("target", ArgumentType::Player),
+    );
+    registry.register(
+        Command::new("say", say)
+            .permission(2)
+            .arg("message", ArgumentType::Message),
+    );
+    registry.register(Command::new("save-all", save_all).permission(4));
+    registry.register(Command::new("stop", stop).permission(4));
+}
+
+fn help(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let filter = ctx.get("command").and_then(|arg| arg.as_str());
+        let permission = game.permission_level(ctx.sender);
+        let usages: Vec<String> = game
+            .commands
+            .available(permission)
+            .filter(|cmd| {
+                filter
+                    .map(|name| cmd.names().any(|n| n == name))
+                    .unwrap_or(true)
+            })
+            .map(|cmd| cmd.usage())
+            .collect();
+
+        if usages.is_empty() {
+            return Err(CommandError::UnknownCommand);
+        }
+
+        for usage in usages {
+            game.send_feedback(ctx.sender, &usage).await;
+        }
+        Ok(())
+    }
+    .boxed()
+}
+
+fn kick(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let reason = ctx
+            .get("reason")
+            .and_then(|arg| arg.as_str())
+            .unwrap_or("Kicked by an operator");
+        let target = game
+            .client_by_name_mut(ctx.string("target"))
+            .ok_or_else(no_player)?;
+
+        target.kick(reason).await?;
+        let message = format!("Kicked {}: {}", target.name, reason);
+        game.send_feedback(ctx.sender, &message).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn list(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let names = game.joined_names();
+        let message = format!(
+            "There are {} players online: {}",
+            names.len(),
+            names.join(", ")
+        );
+        game.send_feedback(ctx.sender, &message).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn me(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let name = game.sender_name(ctx.sender)?;
+        let action = ctx.string("action");
+        let component = chat::translate(
+            "chat.type.emote",
+            vec![chat::text(&name), chat::text(action)],
+        );
+        game.broadcast_chat(&component, ChatPosition::Chat).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn msg(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let name = game.sender_name(ctx.sender)?;
+        let message = ctx.string("message");
+        let target = game
+            .client_by_name_mut(ctx.string("target"))
+            .ok_or_else(no_player)?;
+
+        let mut incoming = chat::translate(
+            "commands.message.display.incoming",
+            vec![chat::text(&name), chat::text(message)],
+        );
+        incoming["color"] = "gray".into();
+        incoming["italic"] = true.into();
+        target.send_chat(&incoming, ChatPosition::System).await?;
+
+        let outgoing = format!("You whisper to {}: {}", target.name, message);
+        game.send_feedback(ctx.sender, &outgoing).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn op(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let (uuid, name) = game
+            .profile_by_name(ctx.string("target"))
+            .ok_or_else(no_player)?;
+        let entry = OpEntry {
+            uuid,
+            name: name.clone(),
+            level: MAX_PERMISSION_LEVEL,
+            bypasses_player_limit: false,
+        };
+
+        let access = game.access.clone();
+        if !access.op(entry).await? {
+            return Err(CommandError::Failed(
+                "Nothing changed. The player already is an operator".to_owned(),
+            ));
+        }
+
+        game.set_permission_level(uuid.into(), MAX_PERMISSION_LEVEL)
+            .await?;
+        let message = format!("Made {} a server operator", name);
+        game.send_feedback(ctx.sender, &message).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn deop(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let name = ctx.string("target");
+        let access = game.access.clone();
+        let uuid = access.deop(name).await?.ok_or_else(|| {
+            CommandError::Failed("Nothing changed. The player is not an operator".to_owned())
+        })?;
+
+        game.set_permission_level(uuid.into(), 0).await?;
+        let message = format!("Made {} no longer a server operator", name);
+        game.send_feedback(ctx.sender, &message).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn say(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        let name = game.sender_name(ctx.sender)?;
+        let message = ctx.string("message");
+        let component = chat::translate(
+            "chat.type.announcement",
+            vec![chat::text(&name), chat::text(message)],
+        );
+        game.broadcast_chat(&component, ChatPosition::System).await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn save_all(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        game.send_feedback(ctx.sender, "Saving the game (this may take a moment!)")
+            .await;
+        game.save_world()
+            .await
+            .map_err(|e| CommandError::Failed(format!("Saving failed: {}", e)))?;
+        game.send_feedback(ctx.sender, "Saved the game").await;
+        Ok(())
+    }
+    .boxed()
+}
+
+fn stop(game: &mut Game, ctx: CommandContext) -> BoxFuture<'_, CommandResult> {
+    async move {
+        game.send_feedback(ctx.sender, "Stopping the server").await;
+        game.stop();
+        Ok(())
+    }
+    .boxed()
+}
+
+fn no_player() -> CommandError {
+    CommandError::Failed("No player was found".to_owned())
+}
@@ -0,0 +1,453 @@
+//! Brigadier style commands, declared to the client for completion and highlighting
+
+use std::collections::HashMap;
+
+use futures::future::BoxFuture;
+use thiserror::Error;
+
+pub use argument::{ArgumentType, ArgumentValue, StringKind};
+
+use crate::error::McError;
+use crate::game::{ClientUuid, Game};
+use argument::{starts_with_ignore_case, Reader};
+
+mod access;
+mod argument;
+mod builtin;
+mod tree;
+
+/// Permission level of the console, and the highest op level
+pub const MAX_PERMISSION_LEVEL: u8 = 4;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CommandSender {
+    Player(ClientUuid),
+    Console,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CommandError {
+    #[error("Unknown command")]
+    UnknownCommand,
+
+    #[error("{message} at position {position}")]
+    Syntax { message: String, position: usize },
+
+    #[error("{0}")]
+    Failed(String),
+}
+
+pub type CommandResult = Result<(), CommandError>;
+
+pub type Handler = for<'a> fn(&'a mut Game, CommandContext) -> BoxFuture<'a, CommandResult>;
+
+pub struct CommandContext {
+    pub sender: CommandSender,
+    args: HashMap<&'static str, ArgumentValue>,
+}
+
+#[derive(Clone)]
+pub struct Command {
+    name: &'static str,
+    aliases: Vec<&'static str>,
+    permission: u8,
+    params: Vec<Param>,
+    handler: Handler,
+}
+
+#[derive(Debug, Clone)]
+enum Param {
+    Literal(&'static str),
+    Argument {
+        name: &'static str,
+        kind: ArgumentType,
+        optional: bool,
+    },
+}
+
+#[derive(Default)]
+pub struct CommandRegistry {
+    commands: Vec<Command>,
+}
+
+impl CommandContext {
+    pub fn get(&self, name: &str) -> Option<&ArgumentValue> {
+        self.args.get(name)
+    }
+
+    /// Panics if the argument is missing or of another type, which is a bug in the command
+    pub fn string(&self, name: &str) -> &str {
+        self.get(name)
+            .and_then(ArgumentValue::as_str)
+            .unwrap_or_else(|| panic!("missing string argument '{}'", name))
+    }
+
+    pub fn integer(&self, name: &str) -> Option<i32> {
+        self.get(name).and_then(ArgumentValue::as_integer)
+    }
+}
+
+impl Command {
+    pub fn new(name: &'static str, handler: Handler) -> Self {
+        Self {
+            name,
+            aliases: Vec::new(),
+            permission: 0,
+            params: Vec::new(),
+            handler,
+        }
+    }
+
+    pub fn alias(mut self, alias: &'static str) -> Self {
+        self.aliases.push(alias);
+        self
+    }
+
+    /// Minimum op level needed to run this command
+    pub fn permission(mut self, level: u8) -> Self {
+        self.permission = level;
+        self
+    }
+
+    /// Subcommand keyword
+    pub fn literal(mut self, literal: &'static str) -> Self {
+        self.params.push(Param::Literal(literal));
+        self
+    }
+
+    pub fn arg(self, name: &'static str, kind: ArgumentType) -> Self {
+        self.push_argument(name, kind, false)
+    }
+
+    /// Only valid after all required params
+    pub fn optional_arg(self, name: &'static str, kind: ArgumentType) -> Self {
+        self.push_argument(name, kind, true)
+    }
+
+    fn push_argument(mut self, name: &'static str, kind: ArgumentType, optional: bool) -> Self {
+        debug_assert!(
+            optional || !self.params.iter().any(Param::is_optional),
+            "required param after optional in /{}",
+            self.name
+        );
+        self.params.push(Param::Argument {
+            name,
+            kind,
+            optional,
+        });
+        self
+    }
+
+    /// Name and aliases
+    fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        std::iter::once(self.name).chain(self.aliases.iter().copied())
+    }
+
+    /// Whether the command can be run without the params from this index onwards
+    fn executable_without(&self, idx: usize) -> bool {
+        self.params[idx..].iter().all(Param::is_optional)
+    }
+
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+        for param in &self.params {
+            match param {
+                Param::Literal(literal) => usage.push_str(&format!(" {}", literal)),
+                Param::Argument {
+                    name,
+                    optional: false,
+                    ..
+                } => usage.push_str(&format!(" <{}>", name)),
+                Param::Argument {
+                    name,
+                    optional: true,
+                    ..
+                } => usage.push_str(&format!(" [<{}>]", name)),
+            }
+        }
+
+        usage
+    }
+
+    /// Parses the input following the command name
+    fn parse_params(
+        &self,
+        reader: &mut Reader,
+    ) -> Result<HashMap<&'static str, ArgumentValue>, CommandError> {
+        let mut args = HashMap::new();
+        for (i, param) in self.params.iter().enumerate() {
+            if reader.is_empty() && self.executable_without(i) {
+                break;
+            }
+
+            if !reader.expect_separator() {
+                return Err(CommandError::Syntax {
+                    message: "Unknown or incomplete command".to_owned(),
+                    position: reader.cursor(),
+                });
+            }
+
+            match param {
+                Param::Literal(literal) => {
+                    let position = reader.cursor();
+                    if reader.read_word() != *literal {
+                        return Err(CommandError::Syntax {
+                            message: format!("Expected '{}'", literal),
+                            position,
+                        });
+                    }
+                }
+                Param::Argument { name, kind, .. } => {
+                    args.insert(*name, kind.parse(reader)?);
+                }
+            }
+        }
+
+        if reader.is_empty() {
+            Ok(args)
+        } else {
+            Err(CommandError::Syntax {
+                message: "Incorrect argument for command".to_owned(),
+                position: reader.cursor(),
+            })
+        }
+    }
+}
+
+impl Param {
+    fn is_optional(&self) -> bool {
+        matches!(self, Param::Argument { optional: true, .. })
+    }
+}
+
+impl CommandRegistry {
+    /// Registry with all the built-in commands
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+        builtin::register(&mut registry);
+        access::register(&mut registry);
+        registry
+    }
+
+    /// Commands with the same name are tried in registration order
+    pub fn register(&mut self, command: Command) {
+        self.commands.push(command);
+    }
+
+    /// Commands the given permission level can run
+    pub fn available(&self, permission: u8) -> impl Iterator<Item = &Command> {
+        self.commands
+            .iter()
+            .filter(move |cmd| cmd.permission <= permission)
+    }
+
+    /// Finds the command to run for the input, without the leading slash
+    pub fn parse(
+        &self,
+        input: &str,
+        permission: u8,
+        sender: CommandSender,
+    ) -> Result<(Handler, CommandContext), CommandError> {
+        let mut reader = Reader::new(input);
+        let name = reader.read_word();
+        let after_name = reader.cursor();
+
+        // report the error from the overload that got furthest
+        let mut best_error = CommandError::UnknownCommand;
+        let mut best_position = 0;
+        for command in self
+            .available(permission)
+            .filter(|cmd| cmd.names().any(|n| n == name))
+        {
+            let mut reader = Reader::new(input);
+            reader.read_word();
+            match command.parse_params(&mut reader) {
+                Ok(args) => return Ok((command.handler, CommandContext { sender, args })),
+                Err(err) => {
+                    let position = match &err {
+                        CommandError::Syntax { position, .. } => *position,
+                        _ => after_name,
+                    };
+                    if position >= best_position {
+                        best_position = position;
+                        best_error = err;
+                    }
+                }
+            }
+        }
+
+        Err(best_error)
+    }
+
+    /// Suggestions for the last word of the input, without the leading slash, along with the
+    /// index in the input that they replace from
+    pub fn complete(
+        &self,
+        input: &str,
+        permission: u8,
+        players: &[String],
+    ) -> (usize, Vec<String>) {
+        let mut suggestions = Vec::new();
+
+        let mut reader = Reader::new(input);
+        let name = reader.read_word();
+        if reader.is_empty() {
+            for command in self.available(permission) {
+                suggestions.extend(
+                    command
+                        .names()
+                        .filter(|n| starts_with_ignore_case(n, name))
+                        .map(str::to_owned),
+                );
+            }
+
+            suggestions.sort();
+            suggestions.dedup();
+            return (0, suggestions);
+        }
+
+        let mut start = input.len();
+        for command in self
+            .available(permission)
+            .filter(|cmd| cmd.names().any(|n| n == name))
+        {
+            let mut reader = Reader::new(input);
+            reader.read_word();
+
+            for param in &command.params {
+                if !reader.expect_separator() {
+                    break;
+                }
+
+                let partial = reader.remaining();
+                if !partial.contains(' ') {
+                    start = reader.cursor();
+                    match param {
+                        Param::Literal(literal) if starts_with_ignore_case(literal, partial) => {
+                            suggestions.push((*literal).to_owned())
+                        }
+                        Param::Literal(_) => {}
+                        Param::Argument { kind, .. } => {
+                            suggestions.extend(kind.suggest(partial, players))
+                        }
+                    }
+                    break;
+                }
+
+                let parsed = match param {
+                    Param::Literal(literal) => reader.read_word() == *literal,
+                    Param::Argument { kind, .. } => kind.parse(&mut reader).is_ok(),
+                };
+                if !parsed {
+                    break;
+                }
+            }
+        }
+
+        suggestions.sort();
+        suggestions.dedup();
+        (start, suggestions)
+    }
+}
+
+impl From<McError> for CommandError {
+    fn from(err: McError) -> Self {
+        CommandError::Failed(err.to_string())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    fn noop(_: &mut Game, _: CommandContext) -> BoxFuture<'_, CommandResult> {
+        async { Ok(()) }.boxed()
+    }
+
+    pub fn registry() -> CommandRegistry {
+        let mut registry = CommandRegistry::default();
+        registry.register(
+            Command::new("time", noop)
+                .permission(2)
+                .literal("set")
+                .arg("time", ArgumentType::integer()),
+        );
+        registry.register(
+            Command::new("time", noop)
+                .permission(2)
+                .literal("add")
+                .arg("time", ArgumentType::integer()),
+        );
+        registry.register(
+            Command::new("msg", noop)
+                .alias("tell")
+                .arg("target", ArgumentType::Player)
+                .arg("message", ArgumentType::Message),
+        );
+        registry.register(
+            Command::new("help", noop)
+                .optional_arg("command", ArgumentType::String(StringKind::Word)),
+        );
+        registry
+    }
+
+    #[test]
+    fn dispatch() {
+        let registry = registry();
+        let sender = CommandSender::Console;
+
+        let (_, ctx) = registry.parse("tell Steve hello there", 0, sender).unwrap();
+        assert_eq!(ctx.string("target"), "Steve");
+        assert_eq!(ctx.string("message"), "hello there");
+
+        let (_, ctx) = registry.parse("time set 1000", 4, sender).unwrap();
+        assert_eq!(ctx.integer("time"), Some(1000));
+
+        assert!(registry.parse("help", 0, sender).is_ok());
+        assert!(registry.parse("help msg", 0, sender).is_ok());
+
+        // not permitted is indistinguishable from not existing
+        assert_eq!(
+            registry.parse("time set 1000", 0, sender).err(),
+            Some(CommandError::UnknownCommand)
+        );
+        assert_eq!(
+            registry.parse("nope", 4, sender).err(),
+            Some(CommandError::UnknownCommand)
+        );
+
+        match registry.parse("time set soon", 4, sender) {
+            Err(CommandError::Syntax { position, .. }) => assert_eq!(position, 9),
+            _ => panic!("expected syntax error"),
+        }
+        assert!(registry.parse("msg Steve", 0, sender).is_err());
+    }
+
+    #[test]
+    fn completion() {
+        let registry = registry();
+        let players = vec!["Steve".to_owned(), "Alex".to_owned()];
+
+        assert_eq!(
+            registry.complete("t", 0, &players),
+            (0, vec!["tell".to_owned()])
+        );
+        assert_eq!(
+            registry.complete("t", 4, &players),
+            (0, vec!["tell".to_owned(), "time".to_owned()])
+        );
+        assert_eq!(
+            registry.complete("msg st", 0, &players),
+            (4, vec!["Steve".to_owned()])
+        );
+        assert_eq!(
+            registry.complete("time s", 4, &players),
+            (5, vec!["set".to_owned()])
+        );
+        assert_eq!(
+            registry.complete("msg Steve hi", 0, &players).1,
+            Vec::<String>::new()
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! Command graph sent in DeclareCommands
+
+use crate::connection::ClientBoundPacket;
+use crate::game::command::{ArgumentType, CommandRegistry, Param};
+use crate::packet::play::client as play;
+use crate::packet::PacketBuilder;
+
+const NODE_ROOT: i8 = 0;
+const NODE_LITERAL: i8 = 1;
+const NODE_ARGUMENT: i8 = 2;
+const FLAG_EXECUTABLE: i8 = 0x04;
+const FLAG_SUGGESTIONS: i8 = 0x10;
+
+#[derive(Debug, PartialEq)]
+enum NodeKind {
+    Root,
+    Literal(&'static str),
+    Argument {
+        name: &'static str,
+        kind: ArgumentType,
+    },
+}
+
+#[derive(Debug)]
+struct Node {
+    kind: NodeKind,
+    executable: bool,
+    children: Vec<usize>,
+}
+
+/// Flattened graph, with the root at index 0
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn new() -> Self {
+        Self {
+            nodes: vec![Node {
+                kind: NodeKind::Root,
+                executable: false,
+                children: Vec::new(),
+            }],
+        }
+    }
+
+    /// Child of the given node with this kind, merging with an existing one
+    fn child(&mut self, parent: usize, kind: NodeKind) -> usize {
+        let existing = self.nodes[parent]
+            .children
+            .iter()
+            .copied()
+            .find(|idx| self.nodes[*idx].kind == kind);
+
+        existing.unwrap_or_else(|| {
+            let idx = self.nodes.len();
+            self.nodes.push(Node {
+                kind,
+                executable: false,
+                children: Vec::new(),
+            });
+            self.nodes[parent].children.push(idx);
+            idx
+        })
+    }
+
+    fn encode(&self) -> ClientBoundPacket {
+        let mut packet = PacketBuilder::new(play::DeclareCommands::ID);
+        packet.var_int(self.nodes.len() as i32);
+
+        for node in &self.nodes {
+            let (node_type, suggestions) = match node.kind {
+                NodeKind::Root => (NODE_ROOT, false),
+                NodeKind::Literal(_) => (NODE_LITERAL, false),
+                NodeKind::Argument { kind, .. } => (NODE_ARGUMENT, kind.asks_server()),
+            };
+
+            let mut flags = node_type;
+            if node.executable {
+                flags |= FLAG_EXECUTABLE;
+            }
+            if suggestions {
+                flags |= FLAG_SUGGESTIONS;
+            }
+
+            packet.byte(flags).var_int(node.children.len() as i32);
+            for child in &node.children {
+                packet.var_int(*child as i32);
+            }
+
+            match node.kind {
+                NodeKind::Root => {}
+                NodeKind::Literal(name) => {
+                    packet.string(name);
+                }
+                NodeKind::Argument { name, kind } => {
+                    packet.string(name);
+                    kind.encode(&mut packet);
+                }
+            }
+
+            if suggestions {
+                packet.string("minecraft:ask_server");
+            }
+        }
+
+        packet.var_int(0).build()
+    }
+}
+
+impl CommandRegistry {
+    /// DeclareCommands packet with the commands available at the given permission level
+    pub fn declare_packet(&self, permission: u8) -> ClientBoundPacket {
+        self.tree(permission).encode()
+    }
+
+    fn tree(&self, permission: u8) -> Tree {
+        let mut tree = Tree::new();
+
+        for command in self.available(permission) {
+            for name in command.names() {
+                let mut node = tree.child(0, NodeKind::Literal(name));
+                if command.executable_without(0) {
+                    tree.nodes[node].executable = true;
+                }
+
+                for (i, param) in command.params.iter().enumerate() {
+                    let kind = match param {
+                        Param::Literal(literal) => NodeKind::Literal(literal),
+                        Param::Argument { name, kind, .. } => {
+                            NodeKind::Argument { name, kind: *kind }
+                        }
+                    };
+
+                    node = tree.child(node, kind);
+                    if command.executable_without(i + 1) {
+                        tree.nodes[node].executable = true;
+                    }
+                }
+            }
+        }
+
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::command::tests::registry;
+
+    use super::*;
+
+    #[test]
+    fn overloads_share_nodes() {
+        let tree = registry().tree(4);
+        let root = &tree.nodes[0];
+
+        // time, msg, tell, help
+        assert_eq!(root.children.len(), 4);
+
+        let time = &tree.nodes[root.children[0]];
+        assert_eq!(time.kind, NodeKind::Literal("time"));
+        assert!(!time.executable);
+        assert_eq!(time.children.len(), 2); // set and add
+
+        let help = &tree.nodes[root.children[3]];
+        assert!(help.executable);
+        assert!(tree.nodes[help.children[0]].executable);
+    }
+}
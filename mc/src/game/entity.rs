@@ -0,0 +1,260 @@
+use crate::connection::ClientBoundPacket;
+use crate::game::{ClientUuid, PlayerName, ProfileProperty};
+use crate::packet::play::client as play;
+use crate::packet::PacketBuilder;
+
+pub type EntityId = i32;
+
+/// Movement is sent as a delta in 1/4096ths of a block if it fits in a short
+const DELTA_SCALE: f64 = 4096.0;
+
+/// PlayerInfo actions
+const PLAYER_INFO_ADD: i32 = 0;
+const PLAYER_INFO_LATENCY: i32 = 2;
+const PLAYER_INFO_REMOVE: i32 = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Location {
+    pub position: (f64, f64, f64),
+    /// Yaw and pitch in degrees
+    pub rotation: (f32, f32),
+    pub on_ground: bool,
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Self {
+            position: (0.0, 0.0, 0.0),
+            rotation: (0.0, 0.0),
+            on_ground: false,
+        }
+    }
+}
+
+/// Degrees to steps of 1/256 of a full turn
+pub fn angle(degrees: f32) -> i8 {
+    (degrees.rem_euclid(360.0) / 360.0 * 256.0) as u8 as i8
+}
+
+/// Adds players to the tab list, which must happen before they can be spawned
+pub fn player_info_add<'a>(
+    players: impl ExactSizeIterator<Item = (ClientUuid, &'a PlayerName, &'a [ProfileProperty], i32)>,
+) -> ClientBoundPacket {
+    let mut packet = PacketBuilder::new(play::PlayerInfo::ID);
+    packet
+        .var_int(PLAYER_INFO_ADD)
+        .var_int(players.len() as i32);
+
+    for (uuid, name, properties, ping) in players {
+        packet
+            .uuid(uuid.as_uuid())
+            .string(&name.0)
+            .var_int(properties.len() as i32);
+
+        for property in properties {
+            packet
+                .string(&property.name)
+                .string(&property.value)
+                .bool(property.signature.is_some());
+            if let Some(signature) = &property.signature {
+                packet.string(signature);
+            }
+        }
+
+        packet
+            .var_int(0) // game mode
+            .var_int(ping)
+            .bool(false); // no display name
+    }
+
+    packet.build()
+}
+
+/// Updates the ping shown in the tab list, in milliseconds
+pub fn player_info_latency(
+    players: impl ExactSizeIterator<Item = (ClientUuid, i32)>,
+) -> ClientBoundPacket {
+    let mut packet = PacketBuilder::new(play::PlayerInfo::ID);
+    packet
+        .var_int(PLAYER_INFO_LATENCY)
+        .var_int(players.len() as i32);
+
+    for (uuid, ping) in players {
+        packet.uuid(uuid.as_uuid()).var_int(ping);
+    }
+
+    packet.build()
+}
+
+pub fn player_info_remove(uuid: ClientUuid) -> ClientBoundPacket {
+    PacketBuilder::new(play::PlayerInfo::ID)
+        .var_int(PLAYER_INFO_REMOVE)
+        .var_int(1)
+        .uuid(uuid.as_uuid())
+        .build()
+}
+
+pub fn spawn_player(
+    entity_id: EntityId,
+    uuid: ClientUuid,
+    location: &Location,
+) -> ClientBoundPacket {
+    let (x, y, z) = location.position;
+    let (yaw, pitch) = location.rotation;
+    PacketBuilder::new(play::NamedEntitySpawn::ID)
+        .var_int(entity_id)
+        .uuid(uuid.as_uuid())
+        .double(x)
+        .double(y)
+        .double(z)
+        .byte(angle(yaw))
+        .byte(angle(pitch))
+        .build()
+}
+
+pub fn destroy(entity_ids: &[EntityId]) -> ClientBoundPacket {
+    let mut packet = PacketBuilder::new(play::EntityDestroy::ID);
+    packet.var_int(entity_ids.len() as i32);
+    for id in entity_ids {
+        packet.var_int(*id);
+    }
+
+    packet.build()
+}
+
+/// Packets to move an entity from one location to another, relative if close enough
+pub fn movement(entity_id: EntityId, from: &Location, to: &Location) -> Vec<ClientBoundPacket> {
+    let mut packets = Vec::with_capacity(2);
+    let moved = from.position != to.position;
+    let rotated = from.rotation != to.rotation;
+
+    let (yaw, pitch) = (angle(to.rotation.0), angle(to.rotation.1));
+    let on_ground = to.on_ground;
+
+    if moved {
+        match relative_delta(from.position, to.position) {
+            Some((d_x, d_y, d_z)) if rotated => packets.push(
+                play::EntityMoveLook {
+                    entity_id: entity_id.into(),
+                    d_x: d_x.into(),
+                    d_y: d_y.into(),
+                    d_z: d_z.into(),
+                    yaw: yaw.into(),
+                    pitch: pitch.into(),
+                    on_ground: on_ground.into(),
+                }
+                .into(),
+            ),
+            Some((d_x, d_y, d_z)) => packets.push(
+                play::RelEntityMove {
+                    entity_id: entity_id.into(),
+                    d_x: d_x.into(),
+                    d_y: d_y.into(),
+                    d_z: d_z.into(),
+                    on_ground: on_ground.into(),
+                }
+                .into(),
+            ),
+            None => {
+                let (x, y, z) = to.position;
+                packets.push(
+                    play::EntityTeleport {
+                        entity_id: entity_id.into(),
+                        x: x.into(),
+                        y: y.into(),
+                        z: z.into(),
+                        yaw: yaw.into(),
+                        pitch: pitch.into(),
+                        on_ground: on_ground.into(),
+                    }
+                    .into(),
+                )
+            }
+        }
+    } else if rotated {
+        packets.push(
+            play::EntityLook {
+                entity_id: entity_id.into(),
+                yaw: yaw.into(),
+                pitch: pitch.into(),
+                on_ground: on_ground.into(),
+            }
+            .into(),
+        );
+    }
+
+    if rotated {
+        // body and head are rotated separately
+        packets.push(
+            play::EntityHeadRotation {
+                entity_id: entity_id.into(),
+                head_yaw: yaw.into(),
+            }
+            .into(),
+        );
+    }
+
+    packets
+}
+
+/// None if the delta is too large and needs a teleport instead
+fn relative_delta(from: (f64, f64, f64), to: (f64, f64, f64)) -> Option<(i16, i16, i16)> {
+    fn delta(from: f64, to: f64) -> Option<i16> {
+        let delta = (to * DELTA_SCALE) as i64 - (from * DELTA_SCALE) as i64;
+        if delta >= i16::MIN as i64 && delta <= i16::MAX as i64 {
+            Some(delta as i16)
+        } else {
+            None
+        }
+    }
+
+    Some((
+        delta(from.0, to.0)?,
+        delta(from.1, to.1)?,
+        delta(from.2, to.2)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angles() {
+        assert_eq!(angle(0.0), 0);
+        assert_eq!(angle(90.0), 64);
+        assert_eq!(angle(180.0), -128);
+        assert_eq!(angle(-90.0), -64);
+        assert_eq!(angle(720.0 + 45.0), 32);
+    }
+
+    #[test]
+    fn deltas() {
+        assert_eq!(
+            relative_delta((0.0, 64.0, 0.0), (1.0, 63.5, -0.25)),
+            Some((4096, -2048, -1024))
+        );
+
+        // just under 8 blocks fits, any further doesn't
+        assert!(relative_delta((0.0, 0.0, 0.0), (7.9, 0.0, 0.0)).is_some());
+        assert!(relative_delta((0.0, 0.0, 0.0), (0.0, 0.0, 8.0)).is_none());
+    }
+
+    #[test]
+    fn movement_packets() {
+        let from = Location::default();
+        assert!(movement(1, &from, &from).is_empty());
+
+        let turned = Location {
+            rotation: (90.0, 0.0),
+            ..from
+        };
+        assert_eq!(movement(1, &from, &turned).len(), 2); // look and head rotation
+
+        let moved = Location {
+            position: (1.0, 0.0, 0.0),
+            ..from
+        };
+        assert_eq!(movement(1, &from, &moved).len(), 1);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::error::{McError, McResult};
+
+/// How often keep-alives are sent
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Clients are disconnected if a keep-alive goes unanswered for this long
+pub const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Outstanding keep-alives and the latency measured from the answered ones
+pub struct KeepAlive {
+    /// Oldest first
+    pending: VecDeque<(i64, Instant)>,
+    last_sent: Option<Instant>,
+    /// Smoothed round trip time in milliseconds, as shown in the tab list
+    ping: i32,
+}
+
+impl KeepAlive {
+    pub fn new() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            last_sent: None,
+            ping: 0,
+        }
+    }
+
+    pub fn ping(&self) -> i32 {
+        self.ping
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.last_sent
+            .map(|sent| now.duration_since(sent) >= KEEP_ALIVE_INTERVAL)
+            .unwrap_or(true)
+    }
+
+    pub fn is_timed_out(&self, now: Instant) -> bool {
+        self.pending
+            .front()
+            .map(|(_, sent)| now.duration_since(*sent) >= KEEP_ALIVE_TIMEOUT)
+            .unwrap_or(false)
+    }
+
+    pub fn on_sent(&mut self, id: i64, now: Instant) {
+        self.pending.push_back((id, now));
+        self.last_sent = Some(now);
+    }
+
+    /// Updates the ping from the response, which also answers any older keep-alives
+    pub fn on_response(&mut self, id: i64, now: Instant) -> McResult<()> {
+        let idx = self
+            .pending
+            .iter()
+            .position(|(pending, _)| *pending == id)
+            .ok_or(McError::IncorrectKeepAlive(id))?;
+
+        let (_, sent) = self.pending[idx];
+        self.pending.drain(..=idx);
+
+        // weighted towards the previous value, as in vanilla
+        let latency = now.duration_since(sent).as_millis() as i32;
+        self.ping = (self.ping * 3 + latency) / 4;
+        Ok(())
+    }
+}
+
+/// Random ID so responses can't be forged ahead of time
+pub fn random_id() -> McResult<i64> {
+    let mut bytes = [0u8; 8];
+    openssl::rand::rand_bytes(&mut bytes)?;
+    Ok(i64::from_be_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_and_timeout() {
+        let start = Instant::now();
+        let mut keep_alive = KeepAlive::new();
+        assert!(keep_alive.is_due(start));
+
+        keep_alive.on_sent(100, start);
+        assert!(!keep_alive.is_due(start + Duration::from_secs(1)));
+        assert!(keep_alive.is_due(start + KEEP_ALIVE_INTERVAL));
+
+        keep_alive.on_sent(200, start + KEEP_ALIVE_INTERVAL);
+        assert!(keep_alive.is_timed_out(start + KEEP_ALIVE_TIMEOUT));
+
+        // answering the newest also clears the oldest
+        assert!(keep_alive.on_response(300, start).is_err());
+        keep_alive
+            .on_response(
+                200,
+                start + KEEP_ALIVE_INTERVAL + Duration::from_millis(400),
+            )
+            .unwrap();
+        assert_eq!(keep_alive.ping(), 100);
+        assert!(!keep_alive.is_timed_out(start + KEEP_ALIVE_TIMEOUT * 2));
+        assert!(keep_alive.on_response(100, start).is_err());
+    }
+}
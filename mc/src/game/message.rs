@@ -1,10 +1,16 @@
 use crate::connection::ClientBoundPacket;
 use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use uuid::Uuid;
 
 pub enum ClientMessage {
     NewClient {
         name: String,
+        properties: Vec<ProfileProperty>,
+        address: IpAddr,
         outgoing: UnboundedSender<ClientBoundPacket>,
     },
 
@@ -18,16 +24,74 @@ pub enum ClientMessage {
     PlayerDisconnected,
 
     VerifyKeepAlive(i64),
+
+    /// Chat message or command typed by the player
+    Chat(String),
+
+    /// Completion request for a partially typed command
+    TabComplete {
+        transaction_id: i32,
+        text: String,
+    },
+
+    /// Position and/or rotation update from one of the movement packets
+    Movement {
+        position: Option<(f64, f64, f64)>,
+        rotation: Option<(f32, f32)>,
+        on_ground: bool,
+    },
+
+    /// View distance from the client's settings, the only one used
+    ViewDistance(i32),
 }
 
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
 pub struct ClientUuid(Uuid);
 
-pub type ClientMessageSender = UnboundedSender<(ClientUuid, ClientMessage)>;
-pub type ClientMessageReceiver = UnboundedReceiver<(ClientUuid, ClientMessage)>;
+/// Tells apart connections of the same player, so messages still queued from one replaced by a
+/// newer login aren't applied to the new one
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub struct ConnectionId(u64);
+
+/// Game profile property from the session server, such as the player's skin
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct ProfileProperty {
+    pub name: String,
+    /// Base64 encoded
+    pub value: String,
+    /// Mojang's signature of the value, without which the client ignores textures
+    pub signature: Option<String>,
+}
+
+pub type ClientMessageSender = UnboundedSender<(ClientUuid, ConnectionId, ClientMessage)>;
+pub type ClientMessageReceiver = UnboundedReceiver<(ClientUuid, ConnectionId, ClientMessage)>;
+
+/// Commands typed into the server console, without a leading slash
+pub type ConsoleCommandSender = UnboundedSender<String>;
+pub type ConsoleCommandReceiver = UnboundedReceiver<String>;
+
+impl ConnectionId {
+    /// Unique for the life of the server
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        ConnectionId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
 
 impl From<Uuid> for ClientUuid {
     fn from(uuid: Uuid) -> Self {
         ClientUuid(uuid)
     }
 }
+
+impl ClientUuid {
+    pub fn as_uuid(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl Display for ClientUuid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0.to_hyphenated_ref(), f)
+    }
+}
@@ -1,123 +1,414 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::hint::unreachable_unchecked;
-use std::time::Duration;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Instant;
 
-use async_std::sync::Arc;
+use async_std::sync::{Arc, RwLock};
 use async_std::task;
-use async_std::task::JoinHandle;
-use chashmap::{CHashMap, WriteGuard};
-use futures::{channel::mpsc::UnboundedSender, SinkExt, StreamExt};
+use futures::{
+    channel::mpsc::{self, UnboundedSender},
+    future, pin_mut, select,
+    stream::{self, BoxStream},
+    Future, FutureExt, SinkExt, StreamExt,
+};
 use log::*;
 use minecraft_server_protocol::types::*;
+use uuid::Uuid;
 
-pub use message::{ClientMessage, ClientMessageReceiver, ClientMessageSender, ClientUuid};
+pub use message::{
+    ClientMessage, ClientMessageReceiver, ClientMessageSender, ClientUuid, ConnectionId,
+    ConsoleCommandReceiver, ConsoleCommandSender, ProfileProperty,
+};
 
-use crate::connection::ClientBoundPacket;
+use crate::access::AccessLists;
+use crate::chat::{self, ChatPosition};
+use crate::connection::{offline_uuid, ClientBoundPacket};
 use crate::error::{McError, McResult};
 use crate::packet::play::client as play;
 use crate::packet::play::client::KickDisconnect;
-use crate::packet::PlayerPositionAndLookExt;
+use crate::packet::{ChatExt, PacketBuilder, PlayerPositionAndLookExt};
 use crate::packet::{DisconnectExt, KeepAliveExt};
+use crate::server::ServerData;
+use crate::world::{ChunkPos, GeneratorSettings, World, BLOCK_REPORT_PATH};
+use command::{CommandError, CommandRegistry, CommandSender, MAX_PERMISSION_LEVEL};
+use entity::{EntityId, Location};
+use keep_alive::KeepAlive;
+use tick::{TickClock, TickStats, TICKS_PER_SECOND};
+use view::{ClientView, ViewChange, MIN_VIEW_DISTANCE};
 
 // TODO generic sinks
 
+pub mod command;
+mod entity;
+mod keep_alive;
 mod message;
+mod tick;
+mod view;
 
 struct Client {
+    /// Messages from other connections with the same UUID are stale
+    connection: ConnectionId,
     outgoing: UnboundedSender<ClientBoundPacket>,
+    /// Chunk packets are sent in order by a separate task as they're loaded, so unloads can't
+    /// overtake the chunks they're for
+    chunk_queue: UnboundedSender<BoxStream<'static, ClientBoundPacket>>,
     name: PlayerName,
+    /// Skin and cape, empty in offline mode
+    properties: Vec<ProfileProperty>,
+    address: IpAddr,
+    entity_id: EntityId,
+
+    /// Set once the client has been sent the world, until then it's not sent any other packets
+    joined: bool,
+
+    /// Set once the client has been kicked, after which it's only waiting to disconnect
+    kicked: bool,
+
+    /// Op level, 0 for regular players
+    permission_level: u8,
 
     // TODO uuid to name lookup
     /// Teleport ID sent to client that should be verified with a TeleportConfirm
     next_teleport_id: Option<i32>,
 
-    keep_alive: JoinHandle<()>,
+    keep_alive: KeepAlive,
+
+    /// Chunks the client has loaded
+    view: ClientView,
+
+    location: Location,
+
+    /// Location last sent to other players
+    broadcast_location: Location,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct PlayerName(String);
 
+/// Names of joined players, kept up to date by the game for use outside of it (e.g. the server
+/// list)
+#[derive(Clone, Default)]
+pub struct PlayerList(Arc<RwLock<Vec<(ClientUuid, PlayerName)>>>);
+
 pub struct Game {
-    clients: Arc<CHashMap<ClientUuid, Client>>,
+    clients: HashMap<ClientUuid, Client>,
     clients_rx: ClientMessageReceiver,
+    /// None once stdin is closed
+    console_rx: Option<ConsoleCommandReceiver>,
+    player_list: PlayerList,
+    world: World,
+
+    /// Radius in chunks
+    view_distance: i32,
+
+    tick_stats: TickStats,
+
+    next_entity_id: EntityId,
+
+    commands: Arc<CommandRegistry>,
+
+    /// Whitelist and bans, shared with logins
+    access: AccessLists,
+    /// Whether UUIDs come from Mojang rather than being derived from names
+    online_mode: bool,
+
+    stopping: bool,
+
+    /// Kick message when the server stops
+    shutdown_message: String,
+}
+
+/// What woke up the game loop
+enum GameEvent {
+    Message(Option<(ClientUuid, ConnectionId, ClientMessage)>),
+    Console(Option<String>),
+    Tick,
 }
 
+/// Ticks between time updates sent to clients
+const TIME_UPDATE_INTERVAL: i64 = TICKS_PER_SECOND as i64;
+
+/// Ticks between tab list ping updates
+const LATENCY_UPDATE_INTERVAL: i64 = 30 * TICKS_PER_SECOND as i64;
+
+/// Ticks between saves of modified chunks, as in vanilla
+const AUTOSAVE_INTERVAL: i64 = 5 * 60 * TICKS_PER_SECOND as i64;
+
 impl Game {
-    pub fn new(clients_rx: ClientMessageReceiver) -> Self {
-        Self {
-            clients: Arc::new(CHashMap::with_capacity(64)),
+    pub fn new(
+        clients_rx: ClientMessageReceiver,
+        console_rx: ConsoleCommandReceiver,
+        server_data: &ServerData,
+    ) -> McResult<Self> {
+        let config = server_data.config();
+        let settings = GeneratorSettings {
+            level_type: config.level_type.clone(),
+            preset: config.generator_settings.clone(),
+            seed: config.level_seed.clone(),
+        };
+        let world = World::load(
+            Path::new(&config.level_name),
+            Path::new(BLOCK_REPORT_PATH),
+            &settings,
+        )?;
+
+        Ok(Self {
+            clients: HashMap::with_capacity(64),
             clients_rx,
-        }
+            console_rx: Some(console_rx),
+            player_list: server_data.player_list().clone(),
+            world,
+            view_distance: config.view_distance as i32,
+            tick_stats: TickStats::new(),
+            next_entity_id: 1,
+            commands: Arc::new(CommandRegistry::with_builtins()),
+            access: server_data.access().clone(),
+            online_mode: config.online_mode,
+            stopping: false,
+            shutdown_message: config.shutdown_message.clone(),
+        })
     }
 
+    /// Runs the game loop until the server is stopped
     pub async fn run(mut self) -> McResult<()> {
-        // client message loop
-        task::spawn(async move {
-            loop {
-                if let Some((uuid, mut msg)) = self.clients_rx.next().await {
-                    // special case where client doesn't exist already
-                    if let ClientMessage::NewClient { .. } = &msg {
-                        // take ownership of this msg and replace with body-less variant
-
-                        let new_client = std::mem::replace(&mut msg, ClientMessage::PlayerJoined);
-                        match new_client {
-                            ClientMessage::NewClient { name, outgoing } => {
-                                self.add_player(uuid, name, outgoing).await;
-                            }
-                            _ => unsafe {
-                                // just checked
-                                unreachable_unchecked()
-                            },
-                        };
+        // ticks at a fixed rate, handling client messages and console commands in between
+        let mut clock = TickClock::new();
+        while !self.stopping {
+            let event = {
+                let (clients_rx, console_rx) = (&mut self.clients_rx, &mut self.console_rx);
+                let message = clients_rx.next().fuse();
+                let console = async move {
+                    match console_rx {
+                        Some(rx) => GameEvent::Console(rx.next().await),
+                        None => future::pending().await,
                     }
-                    // special case where client is removed with no further processing
-                    else if let ClientMessage::PlayerDisconnected = &msg {
-                        self.remove_player(uuid).await;
+                }
+                .fuse();
+                let tick = task::sleep(clock.until_next()).fuse();
+                pin_mut!(message, console, tick);
 
-                        // nothing else to do
-                        continue;
+                select! {
+                    msg = message => GameEvent::Message(msg),
+                    event = console => event,
+                    _ = tick => GameEvent::Tick,
+                }
+            };
+
+            match event {
+                GameEvent::Message(Some((uuid, connection, msg))) => {
+                    self.process_message(uuid, connection, msg).await
+                }
+                GameEvent::Message(None) => {
+                    info!("client message channel closed, stopping game loop");
+                    break;
+                }
+                GameEvent::Console(Some(command)) => {
+                    self.run_command(CommandSender::Console, &command).await
+                }
+                GameEvent::Console(None) => {
+                    debug!("console closed");
+                    self.console_rx = None;
+                }
+                GameEvent::Tick => {
+                    let start = Instant::now();
+                    self.tick().await;
+                    self.tick_stats.record(start.elapsed());
+
+                    if let Some(skipped) = clock.advance() {
+                        self.tick_stats.record_skipped(skipped);
                     }
+                    self.tick_stats.maybe_report();
+                }
+            }
+        }
 
-                    let result = match self.client_mut(uuid) {
-                        Err(err) => Err(err),
-                        Ok(client) => self.handle_message(client, msg).await,
-                    };
+        info!("stopping server, kicking {} players", self.clients.len());
+        let message = std::mem::take(&mut self.shutdown_message);
+        for client in self.clients.values_mut() {
+            if let Err(err) = client.kick(&message).await {
+                warn!("failed to kick {}: {}", client.name, err);
+            }
+        }
 
-                    if let Err(err) = result {
-                        error!("error handling message for client {:?}: {}", uuid, err);
+        // TODO save player data once it's persisted
+        self.save_world().await?;
 
-                        match self.client_mut(uuid) {
-                            Err(err) => warn!("can't kick client: {}", err),
-                            Ok(mut client) => {
-                                info!("kicking player {} with error message", client.name.0);
-                                client.kick_with_error(err).await;
-                            }
-                        }
+        Ok(())
+    }
+
+    /// Stops the game loop after the current event
+    fn stop(&mut self) {
+        self.stopping = true;
+    }
+
+    /// Writes modified chunks and level data to disk on a worker thread, the returned future only
+    /// needs awaiting to know when it's done
+    fn save_world(&mut self) -> impl Future<Output = McResult<()>> + Send + 'static {
+        let start = Instant::now();
+        let save = self.world.save();
+        async move {
+            let saved = save.await?;
+            info!("saved {} chunks in {:?}", saved, start.elapsed());
+            Ok(())
+        }
+    }
+
+    async fn process_message(
+        &mut self,
+        uuid: ClientUuid,
+        connection: ConnectionId,
+        mut msg: ClientMessage,
+    ) {
+        // special case where client doesn't exist already
+        if let ClientMessage::NewClient { .. } = &msg {
+            // take ownership of this msg and replace with body-less variant
+
+            let new_client = std::mem::replace(&mut msg, ClientMessage::PlayerJoined);
+            match new_client {
+                ClientMessage::NewClient {
+                    name,
+                    properties,
+                    address,
+                    outgoing,
+                } => {
+                    self.add_player(uuid, connection, name, properties, address, outgoing)
+                        .await;
+                }
+                _ => unsafe {
+                    // just checked
+                    unreachable_unchecked()
+                },
+            };
+        }
+        // left over from a connection replaced by a newer login
+        else if matches!(self.clients.get(&uuid), Some(client) if client.connection != connection)
+        {
+            debug!("ignoring message from superseded connection of {}", uuid);
+            return;
+        }
+        // special case where client is removed with no further processing
+        else if let ClientMessage::PlayerDisconnected = &msg {
+            self.remove_player(uuid).await;
+
+            // nothing else to do
+            return;
+        }
+
+        if let Err(err) = self.handle_message(uuid, msg).await {
+            error!("error handling message for client {:?}: {}", uuid, err);
+
+            match self.client_mut(uuid) {
+                Err(err) => warn!("can't kick client: {}", err),
+                Ok(client) => {
+                    info!("kicking player {} with error message", client.name.0);
+                    client.kick_with_error(err).await;
+                }
+            }
+        }
+    }
+
+    async fn tick(&mut self) {
+        self.world.tick();
+
+        // relay player movement
+        let mut moved = Vec::new();
+        for (uuid, client) in self.clients.iter_mut().filter(|(_, c)| c.joined) {
+            if client.location != client.broadcast_location {
+                moved.push((
+                    *uuid,
+                    client.entity_id,
+                    client.broadcast_location,
+                    client.location,
+                ));
+                client.broadcast_location = client.location;
+            }
+        }
+
+        for (uuid, entity_id, from, to) in moved {
+            for client in self.joined_clients_except(Some(uuid)) {
+                for packet in entity::movement(entity_id, &from, &to) {
+                    if let Err(err) = client.send_packet(packet).await {
+                        warn!("failed to send movement to {}: {}", client.name, err);
                     }
                 }
             }
-        });
+        }
 
-        Ok(())
+        self.send_keep_alives().await;
+
+        if self.world.age() % LATENCY_UPDATE_INTERVAL == 0 {
+            let pings: Vec<_> = self
+                .clients
+                .iter()
+                .filter(|(_, c)| c.joined)
+                .map(|(uuid, c)| (*uuid, c.keep_alive.ping()))
+                .collect();
+
+            for client in self.joined_clients_except(None) {
+                let packet = entity::player_info_latency(pings.iter().copied());
+                if let Err(err) = client.send_packet(packet).await {
+                    warn!("failed to send latency to {}: {}", client.name, err);
+                }
+            }
+        }
+
+        if self.world.age() % TIME_UPDATE_INTERVAL == 0 {
+            let (age, time_of_day) = (self.world.age(), self.world.time_of_day());
+            for client in self.joined_clients_except(None) {
+                if let Err(err) = client.send_time(age, time_of_day).await {
+                    warn!("failed to send time to {}: {}", client.name, err);
+                }
+            }
+        }
+
+        if self.world.age() % AUTOSAVE_INTERVAL == 0 {
+            let save = self.save_world();
+            task::spawn(async move {
+                if let Err(err) = save.await {
+                    error!("failed to save the world: {}", err);
+                }
+            });
+        }
+    }
+
+    /// Sends keep-alives to clients that are due one, and kicks those that stopped responding
+    async fn send_keep_alives(&mut self) {
+        let now = Instant::now();
+        for client in self.clients.values_mut().filter(|client| !client.kicked) {
+            let result = if client.keep_alive.is_timed_out(now) {
+                info!("{} timed out", client.name);
+                client.kick("Timed out").await
+            } else if client.keep_alive.is_due(now) {
+                client.send_keep_alive(now).await
+            } else {
+                continue;
+            };
+
+            if let Err(err) = result {
+                warn!("failed to keep {} alive: {}", client.name, err);
+            }
+        }
     }
 
     async fn add_player(
         &mut self,
         uuid: ClientUuid,
+        connection: ConnectionId,
         name: String,
+        properties: Vec<ProfileProperty>,
+        address: IpAddr,
         outgoing: UnboundedSender<ClientBoundPacket>,
     ) {
-        // spawn keep-alive task
-        let mut keep_alive_tx = outgoing.clone();
-        let keep_alive = task::spawn(async move {
-            loop {
-                task::sleep(Duration::from_secs(1)).await;
-
-                if let Err(err) = keep_alive_tx.send(play::KeepAlive::generate().into()).await {
-                    warn!("failed to send keep-alive: {}", err);
-                    break;
-                }
+        if let Some(existing) = self.clients.get_mut(&uuid) {
+            info!("{} logged in again, kicking the old connection", name);
+            if let Err(err) = existing.kick("You logged in from another location").await {
+                warn!("failed to kick old connection of {}: {}", name, err);
             }
-        });
+
+            self.remove_player(uuid).await;
+        }
 
         // add to clients map
         let count = self.clients.len();
@@ -127,76 +418,421 @@ impl Game {
             count + 1
         );
 
+        let name = PlayerName(name);
+        self.player_list.add(uuid, name.clone()).await;
+
+        let entity_id = self.next_entity_id;
+        self.next_entity_id += 1;
+        let permission_level = self.access.op_level(uuid.as_uuid()).await;
+
         let client = Client {
+            connection,
+            chunk_queue: spawn_chunk_sender(outgoing.clone()),
             outgoing,
-            name: PlayerName(name),
-            keep_alive,
+            name,
+            properties,
+            address,
+            entity_id,
+            joined: false,
+            kicked: false,
+            permission_level,
+            keep_alive: KeepAlive::new(),
             next_teleport_id: None,
+            view: ClientView::new(self.view_distance),
+            location: Location::default(),
+            broadcast_location: Location::default(),
         };
         self.clients.insert(uuid, client);
     }
 
+    /// Introduces a newly joined player and everyone else to each other
+    async fn spawn_player(&mut self, uuid: ClientUuid) -> McResult<()> {
+        let client = self.client_mut(uuid)?;
+        client.joined = true;
+        client.broadcast_location = client.location;
+        let (entity_id, name, properties, location, ping) = (
+            client.entity_id,
+            client.name.clone(),
+            client.properties.clone(),
+            client.location,
+            client.keep_alive.ping(),
+        );
+
+        // the tab list includes the new player themselves
+        let info = entity::player_info_add(
+            self.clients
+                .iter()
+                .filter(|(_, c)| c.joined)
+                .map(|(uuid, c)| (*uuid, &c.name, &c.properties[..], c.keep_alive.ping()))
+                .collect::<Vec<_>>()
+                .into_iter(),
+        );
+        let spawns: Vec<_> = self
+            .clients
+            .iter()
+            .filter(|(other, c)| c.joined && **other != uuid)
+            .map(|(other, c)| entity::spawn_player(c.entity_id, *other, &c.broadcast_location))
+            .collect();
+
+        let client = self.client_mut(uuid)?;
+        client.send_packet(info).await?;
+        for spawn in spawns {
+            client.send_packet(spawn).await?;
+        }
+
+        let permission_level = client.permission_level;
+        let commands = self.commands.declare_packet(permission_level);
+        self.client_mut(uuid)?.send_packet(commands).await?;
+
+        for other in self.joined_clients_except(Some(uuid)) {
+            let result = async {
+                other
+                    .send_packet(entity::player_info_add(std::iter::once((
+                        uuid,
+                        &name,
+                        &properties[..],
+                        ping,
+                    ))))
+                    .await?;
+                other
+                    .send_packet(entity::spawn_player(entity_id, uuid, &location))
+                    .await
+            };
+
+            if let Err(err) = result.await {
+                warn!("failed to spawn {} for {}: {}", name, other.name, err);
+            }
+        }
+
+        self.broadcast_chat(&chat::player_joined(&name.0), ChatPosition::System)
+            .await;
+        Ok(())
+    }
+
     async fn remove_player(&mut self, uuid: ClientUuid) {
         match self.clients.remove(&uuid) {
             Some(client) => {
+                self.player_list.remove(uuid).await;
+
                 let count = self.clients.len();
                 info!(
                     "removed player {} from the game, now has {} players",
                     client.name.0, count
                 );
 
-                // stop keep-alive task
-                let _ = client.keep_alive.cancel().await;
+                if client.joined {
+                    for other in self.joined_clients_except(None) {
+                        let result = async {
+                            other
+                                .send_packet(entity::destroy(&[client.entity_id]))
+                                .await?;
+                            other.send_packet(entity::player_info_remove(uuid)).await
+                        };
+
+                        if let Err(err) = result.await {
+                            warn!(
+                                "failed to despawn {} for {}: {}",
+                                client.name, other.name, err
+                            );
+                        }
+                    }
+                }
+
+                if client.joined {
+                    self.broadcast_chat(&chat::player_left(&client.name.0), ChatPosition::System)
+                        .await;
+                }
             }
             None => warn!("player {:?} disconnected but was not joined", uuid),
         };
     }
 
-    async fn handle_message(
-        &self,
-        mut client: WriteGuard<'_, ClientUuid, Client>,
-        msg: ClientMessage,
-    ) -> McResult<()> {
+    async fn handle_message(&mut self, uuid: ClientUuid, msg: ClientMessage) -> McResult<()> {
         use ClientMessage::*;
 
+        let client = self
+            .clients
+            .get_mut(&uuid)
+            .ok_or(McError::NoSuchPlayer(uuid))?;
+
         match msg {
             NewClient { .. } | PlayerDisconnected => unreachable!(),
 
-            PlayerJoined => client.on_player_joined().await.map_err(|err| {
-                error!("failed to join player");
-                err
-            }),
+            PlayerJoined => {
+                client
+                    .on_player_joined(&self.world, self.view_distance)
+                    .await
+                    .map_err(|err| {
+                        error!("failed to join player");
+                        err
+                    })?;
+
+                self.spawn_player(uuid).await
+            }
             VerifyTeleport(id) => client.check_teleport_id(id),
-            VerifyKeepAlive(keep_alive) => {
-                // TODO check keep alive value properly
-                if keep_alive == 4 {
-                    Ok(())
-                } else {
-                    Err(McError::IncorrectKeepAlive(keep_alive))
+            VerifyKeepAlive(id) => client.keep_alive.on_response(id, Instant::now()),
+            Chat(message) => self.on_chat(uuid, message).await,
+            TabComplete {
+                transaction_id,
+                text,
+            } => self.complete_command(uuid, transaction_id, &text).await,
+            Movement {
+                position,
+                rotation,
+                on_ground,
+            } => {
+                client
+                    .on_movement(position, rotation, on_ground, &self.world)
+                    .await
+            }
+            ViewDistance(distance) => {
+                let distance = distance.min(self.view_distance).max(MIN_VIEW_DISTANCE);
+                client.set_view_distance(distance, &self.world).await
+            }
+        }
+    }
+
+    async fn on_chat(&mut self, uuid: ClientUuid, message: String) -> McResult<()> {
+        let length = message.chars().count();
+        if length > chat::MAX_MESSAGE_LENGTH {
+            return Err(McError::ChatTooLong(length));
+        }
+
+        if !message.chars().all(chat::is_allowed_char) {
+            return Err(McError::IllegalChat);
+        }
+
+        let message = message.trim();
+        if message.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(command) = message.strip_prefix('/') {
+            self.run_command(CommandSender::Player(uuid), command).await;
+            return Ok(());
+        }
+
+        let name = self.client_mut(uuid)?.name.clone();
+        info!("<{}> {}", name, message);
+        self.broadcast_chat(&chat::player_message(&name.0, message), ChatPosition::Chat)
+            .await;
+        Ok(())
+    }
+
+    /// Runs a command without the leading slash, reporting errors back to the sender
+    async fn run_command(&mut self, sender: CommandSender, input: &str) {
+        let name = self
+            .sender_name(sender)
+            .unwrap_or_else(|_| "unknown".to_owned());
+        info!("{} issued server command: /{}", name, input);
+
+        let commands = self.commands.clone();
+        let result = match commands.parse(input, self.permission_level(sender), sender) {
+            Ok((handler, ctx)) => handler(self, ctx).await,
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(()) => {}
+            Err(CommandError::Syntax { message, position }) => {
+                self.send_error(sender, &message).await;
+                self.send_error(sender, &format!("/{}<--[HERE]", &input[..position]))
+                    .await;
+            }
+            Err(err) => self.send_error(sender, &err.to_string()).await,
+        }
+    }
+
+    async fn complete_command(
+        &mut self,
+        uuid: ClientUuid,
+        transaction_id: i32,
+        text: &str,
+    ) -> McResult<()> {
+        let input = text.strip_prefix('/').unwrap_or(text);
+        let players = self.joined_names();
+        let permission = self.permission_level(CommandSender::Player(uuid));
+        let (start, suggestions) = self.commands.complete(input, permission, &players);
+
+        // range is relative to the full text
+        let offset = text.len() - input.len();
+        let mut packet = PacketBuilder::new(play::TabComplete::ID);
+        packet
+            .var_int(transaction_id)
+            .var_int((start + offset) as i32)
+            .var_int((input.len() - start) as i32)
+            .var_int(suggestions.len() as i32);
+        for suggestion in &suggestions {
+            packet.string(suggestion).bool(false); // no tooltip
+        }
+
+        self.client_mut(uuid)?.send_packet(packet.build()).await
+    }
+
+    fn permission_level(&self, sender: CommandSender) -> u8 {
+        match sender {
+            CommandSender::Player(uuid) => self
+                .clients
+                .get(&uuid)
+                .map(|c| c.permission_level)
+                .unwrap_or(0),
+            CommandSender::Console => MAX_PERMISSION_LEVEL,
+        }
+    }
+
+    fn sender_name(&self, sender: CommandSender) -> McResult<String> {
+        match sender {
+            CommandSender::Player(uuid) => self
+                .clients
+                .get(&uuid)
+                .map(|c| c.name.0.clone())
+                .ok_or(McError::NoSuchPlayer(uuid)),
+            CommandSender::Console => Ok("Server".to_owned()),
+        }
+    }
+
+    async fn send_feedback(&mut self, sender: CommandSender, message: &str) {
+        self.send_to_sender(sender, chat::text(message)).await
+    }
+
+    async fn send_error(&mut self, sender: CommandSender, message: &str) {
+        self.send_to_sender(sender, chat::colored(message, "red"))
+            .await
+    }
+
+    async fn send_to_sender(&mut self, sender: CommandSender, component: serde_json::Value) {
+        match sender {
+            CommandSender::Player(uuid) => {
+                if let Ok(client) = self.client_mut(uuid) {
+                    if let Err(err) = client.send_chat(&component, ChatPosition::System).await {
+                        warn!(
+                            "failed to send command feedback to {}: {}",
+                            client.name, err
+                        );
+                    }
                 }
             }
+            CommandSender::Console => {
+                info!("{}", component["text"].as_str().unwrap_or_default())
+            }
+        }
+    }
+
+    /// Sorted names of joined players
+    fn joined_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .clients
+            .values()
+            .filter(|c| c.joined)
+            .map(|c| c.name.0.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Updates the op level of a player if they're online, and the commands they can see
+    async fn set_permission_level(&mut self, uuid: ClientUuid, level: u8) -> McResult<()> {
+        let commands = self.commands.declare_packet(level);
+        match self.clients.get_mut(&uuid) {
+            Some(client) => {
+                client.permission_level = level;
+                client.send_packet(commands).await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// UUID and name of an online player, or in offline mode of anyone, as they need not be
+    /// looked up
+    fn profile_by_name(&self, name: &str) -> Option<(Uuid, String)> {
+        let online = self
+            .clients
+            .iter()
+            .find(|(_, c)| c.joined && c.name.0.eq_ignore_ascii_case(name))
+            .map(|(uuid, c)| (*uuid.as_uuid(), c.name.0.clone()));
+
+        match online {
+            Some(profile) => Some(profile),
+            None if !self.online_mode => {
+                offline_uuid(name).ok().map(|uuid| (uuid, name.to_owned()))
+            }
+            None => None,
+        }
+    }
+
+    fn clients_by_address_mut(&mut self, address: IpAddr) -> impl Iterator<Item = &mut Client> {
+        self.clients
+            .values_mut()
+            .filter(move |c| c.address == address)
+    }
+
+    fn client_by_name_mut(&mut self, name: &str) -> Option<&mut Client> {
+        self.clients
+            .values_mut()
+            .find(|c| c.joined && c.name.0.eq_ignore_ascii_case(name))
+    }
+
+    /// Sends to all joined players
+    async fn broadcast_chat(&mut self, component: &serde_json::Value, position: ChatPosition) {
+        for client in self.joined_clients_except(None) {
+            if let Err(err) = client.send_chat(component, position).await {
+                warn!("failed to send chat to {}: {}", client.name, err);
+            }
         }
     }
 
-    fn client_mut(&self, uuid: ClientUuid) -> McResult<chashmap::WriteGuard<ClientUuid, Client>> {
+    fn client_mut(&mut self, uuid: ClientUuid) -> McResult<&mut Client> {
         self.clients
             .get_mut(&uuid)
             .ok_or(McError::NoSuchPlayer(uuid))
     }
 
-    // fn client(&self, uuid: ClientUuid) -> McResult<chashmap::ReadGuard<ClientUuid, Client>> {
-    //     self.clients
-    //         .get(&uuid)
-    //         .ok_or_else(|| McError::NoSuchPlayer(uuid))
-    // }
+    fn joined_clients_except(
+        &mut self,
+        except: Option<ClientUuid>,
+    ) -> impl Iterator<Item = &mut Client> {
+        self.clients
+            .iter_mut()
+            .filter(move |(uuid, c)| c.joined && Some(**uuid) != except)
+            .map(|(_, c)| c)
+    }
+}
+
+impl PlayerList {
+    pub async fn count(&self) -> usize {
+        self.0.read().await.len()
+    }
+
+    /// Up to `n` joined players
+    pub async fn sample(&self, n: usize) -> Vec<(ClientUuid, PlayerName)> {
+        self.0.read().await.iter().take(n).cloned().collect()
+    }
+
+    async fn add(&self, uuid: ClientUuid, name: PlayerName) {
+        let mut players = self.0.write().await;
+        players.retain(|(existing, _)| *existing != uuid);
+        players.push((uuid, name));
+    }
+
+    async fn remove(&self, uuid: ClientUuid) {
+        self.0
+            .write()
+            .await
+            .retain(|(existing, _)| *existing != uuid);
+    }
+}
+
+impl Display for PlayerName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
 impl Client {
     async fn kick_with_error(&mut self, error: McError) {
-        if let Err(kick_error) = self
-            .send_packet(KickDisconnect::with_error(&error).into())
-            .await
-        {
+        self.kicked = true;
+        let packet = ClientBoundPacket::from(KickDisconnect::with_error(&error)).disconnecting();
+        if let Err(kick_error) = self.send_packet(packet).await {
             error!(
                 "failed to kick with existing error {}: {}",
                 error, kick_error
@@ -204,11 +840,79 @@ impl Client {
         }
     }
 
+    async fn send_keep_alive(&mut self, now: Instant) -> McResult<()> {
+        let id = keep_alive::random_id()?;
+        self.keep_alive.on_sent(id, now);
+        self.send_packet(play::KeepAlive::new(id).into()).await
+    }
+
+    async fn kick(&mut self, reason: &str) -> McResult<()> {
+        self.kicked = true;
+        let packet = KickDisconnect {
+            reason: StringField::new(chat::text(reason).to_string()),
+        };
+        self.send_packet(ClientBoundPacket::from(packet).disconnecting())
+            .await
+    }
+
+    /// Queues the unloads and the chunks that entered the view, which are sent as they're loaded
+    async fn send_view_change(&mut self, change: ViewChange, world: &World) -> McResult<()> {
+        let still_queued = self.view.still_queued(&change);
+        let unloads: Vec<ClientBoundPacket> = change
+            .unload
+            .into_iter()
+            .map(|pos| {
+                play::UnloadChunk {
+                    chunk_x: pos.x.into(),
+                    chunk_z: pos.z.into(),
+                }
+                .into()
+            })
+            .collect();
+        let loads = world
+            .chunks(change.load, still_queued)
+            .map(|chunk| chunk.to_packet());
+
+        self.chunk_queue
+            .send(stream::iter(unloads).chain(loads).boxed())
+            .await?;
+        Ok(())
+    }
+
     async fn send_packet(&mut self, packet: ClientBoundPacket) -> McResult<()> {
         self.outgoing.send(packet).await?;
         Ok(())
     }
 
+    async fn send_chat(
+        &mut self,
+        component: &serde_json::Value,
+        position: ChatPosition,
+    ) -> McResult<()> {
+        self.send_packet(play::Chat::new(component, position).into())
+            .await
+    }
+
+    async fn send_time(&mut self, age: i64, time_of_day: i64) -> McResult<()> {
+        self.send_packet(
+            play::UpdateTime {
+                age: age.into(),
+                time: time_of_day.into(),
+            }
+            .into(),
+        )
+        .await
+    }
+
+    /// Teleports the client, which ignores its own movement until it confirms
+    async fn teleport(&mut self, position: (f64, f64, f64), teleport_id: i32) -> McResult<()> {
+        self.send_packet(play::Position::new(position, teleport_id).into())
+            .await?;
+        self.location.position = position;
+        self.set_teleport_id(teleport_id);
+        Ok(())
+    }
+
     fn set_teleport_id(&mut self, teleport_id: i32) {
         self.next_teleport_id = Some(teleport_id);
     }
@@ -225,8 +929,7 @@ impl Client {
         }
     }
 
-    #[allow(unused_variables)]
-    async fn on_player_joined(&mut self) -> McResult<()> {
+    async fn on_player_joined(&mut self, world: &World, view_distance: i32) -> McResult<()> {
         macro_rules! send {
             ($packet:expr) => {
                 self.send_packet(ClientBoundPacket::from($packet)).await?;
@@ -234,13 +937,13 @@ impl Client {
         }
 
         send!(play::Login {
-            entity_id: 123.into(),
+            entity_id: self.entity_id.into(),
             game_mode: 0.into(),
             dimension: 0.into(),
-            hashed_seed: 12_345_678.into(),
+            hashed_seed: world.hashed_seed().into(),
             max_players: 0.into(),
-            level_type: StringField::new("default".to_owned()),
-            view_distance: 20.into(),
+            level_type: StringField::new(world.level_type().to_owned()),
+            view_distance: view_distance.into(),
             reduced_debug_info: false.into(),
             enable_respawn_screen: true.into(),
         });
@@ -248,14 +951,118 @@ impl Client {
         // TODO handle held item slot response
         // send!(play::HeldItemSlot { slot: 2.into() });
 
+        let spawn = world.spawn();
         send!(play::SpawnPosition {
-            location: PositionField::new((500, 64, -500)).unwrap(),
+            location: PositionField::new(spawn)?,
         });
 
-        let teleport_id = 1234;
-        send!(play::Position::new((10.0, 100.0, -10.0), teleport_id));
-        self.set_teleport_id(teleport_id);
+        // send terrain around spawn
+        let spawn_chunk = ChunkPos::containing(spawn.0 as f64, spawn.2 as f64);
+        send!(play::UpdateViewPosition {
+            chunk_x: spawn_chunk.x.into(),
+            chunk_z: spawn_chunk.z.into(),
+        });
+
+        let change = self.view.update(spawn_chunk, self.view.distance());
+        self.send_view_change(change, world).await?;
+
+        self.send_time(world.age(), world.time_of_day()).await?;
+
+        let spawn_pos = (spawn.0 as f64 + 0.5, spawn.1 as f64, spawn.2 as f64 + 0.5);
+        self.teleport(spawn_pos, 1234).await?;
 
         Ok(())
     }
+
+    async fn on_movement(
+        &mut self,
+        position: Option<(f64, f64, f64)>,
+        rotation: Option<(f32, f32)>,
+        on_ground: bool,
+        world: &World,
+    ) -> McResult<()> {
+        // movement sent before the client saw the teleport is stale
+        if let Some(teleport_id) = self.next_teleport_id {
+            debug!(
+                "ignoring movement from {} while awaiting teleport {}",
+                self.name, teleport_id
+            );
+            return Ok(());
+        }
+
+        if let Some(position) = position {
+            let (x, y, z) = position;
+            if !(x.is_finite() && y.is_finite() && z.is_finite()) {
+                return Err(McError::BadMovement(position));
+            }
+
+            self.location.position = position;
+            self.update_view_center(ChunkPos::containing(x, z), world)
+                .await?;
+        }
+
+        if let Some((yaw, pitch)) = rotation {
+            self.location.rotation = (yaw % 360.0, pitch.clamp(-90.0, 90.0));
+        }
+
+        self.location.on_ground = on_ground;
+        Ok(())
+    }
+
+    /// Sends the chunks that come into view and unloads those that leave it when the player
+    /// crosses a chunk border
+    async fn update_view_center(&mut self, new_center: ChunkPos, world: &World) -> McResult<()> {
+        if self.view.center() == new_center {
+            return Ok(());
+        }
+
+        self.send_packet(
+            play::UpdateViewPosition {
+                chunk_x: new_center.x.into(),
+                chunk_z: new_center.z.into(),
+            }
+            .into(),
+        )
+        .await?;
+
+        let change = self.view.update(new_center, self.view.distance());
+        self.send_view_change(change, world).await
+    }
+
+    /// Resizes the view to the distance from the client's settings, already capped by the server
+    async fn set_view_distance(&mut self, distance: i32, world: &World) -> McResult<()> {
+        if distance == self.view.distance() {
+            return Ok(());
+        }
+
+        debug!("{} set view distance to {}", self.name, distance);
+        if !self.joined {
+            // the initial chunks are sent on joining
+            self.view = ClientView::new(distance);
+            return Ok(());
+        }
+
+        let change = self.view.update(self.view.center(), distance);
+        self.send_view_change(change, world).await
+    }
+}
+
+/// Spawns the task sending each queued stream of chunk packets in turn, which ends when the queue
+/// is dropped or the client disconnects. Streams replaced by a later view change skip the chunks
+/// they haven't started
+fn spawn_chunk_sender(
+    mut outgoing: UnboundedSender<ClientBoundPacket>,
+) -> UnboundedSender<BoxStream<'static, ClientBoundPacket>> {
+    let (queue, mut streams) = mpsc::unbounded::<BoxStream<'static, ClientBoundPacket>>();
+    task::spawn(async move {
+        while let Some(mut packets) = streams.next().await {
+            while let Some(packet) = packets.next().await {
+                if outgoing.send(packet).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    queue
 }
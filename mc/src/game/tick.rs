@@ -0,0 +1,150 @@
+use std::time::{Duration, Instant};
+
+use log::*;
+
+pub const TICKS_PER_SECOND: u32 = 20;
+
+pub const TICK_DURATION: Duration = Duration::from_millis(1000 / TICKS_PER_SECOND as u64);
+
+/// How far behind schedule the game can fall before giving up on catching up and skipping ticks
+const MAX_LAG: Duration = Duration::from_secs(2);
+
+/// Ticks between stats reports
+const REPORT_INTERVAL: u32 = 60 * TICKS_PER_SECOND;
+
+/// Fixed rate tick schedule that catches up after slow ticks
+pub struct TickClock {
+    next_tick: Instant,
+}
+
+/// Tick timings since the last report
+pub struct TickStats {
+    since: Instant,
+    ticks: u32,
+    total: Duration,
+    longest: Duration,
+    /// Ticks that took longer than [TICK_DURATION]
+    overruns: u32,
+    skipped: u64,
+}
+
+impl TickClock {
+    pub fn new() -> Self {
+        Self {
+            next_tick: Instant::now(),
+        }
+    }
+
+    /// Time until the next tick is due, zero if it's overdue
+    pub fn until_next(&self) -> Duration {
+        self.next_tick.saturating_duration_since(Instant::now())
+    }
+
+    /// Schedules the next tick, returning the number of ticks skipped if the game has fallen too
+    /// far behind
+    pub fn advance(&mut self) -> Option<u64> {
+        self.next_tick += TICK_DURATION;
+
+        let now = Instant::now();
+        let behind = now.saturating_duration_since(self.next_tick);
+        if behind > MAX_LAG {
+            let skipped = (behind.as_millis() / TICK_DURATION.as_millis()) as u64;
+            warn!(
+                "can't keep up! running {}ms behind, skipping {} ticks",
+                behind.as_millis(),
+                skipped
+            );
+            self.next_tick = now;
+            Some(skipped)
+        } else {
+            None
+        }
+    }
+}
+
+impl TickStats {
+    pub fn new() -> Self {
+        Self {
+            since: Instant::now(),
+            ticks: 0,
+            total: Duration::default(),
+            longest: Duration::default(),
+            overruns: 0,
+            skipped: 0,
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        self.ticks += 1;
+        self.total += duration;
+        self.longest = self.longest.max(duration);
+
+        if duration > TICK_DURATION {
+            self.overruns += 1;
+            debug!("tick overran by {:?}", duration - TICK_DURATION);
+        }
+    }
+
+    pub fn record_skipped(&mut self, ticks: u64) {
+        self.skipped += ticks;
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.ticks == 0 {
+            Duration::default()
+        } else {
+            self.total / self.ticks
+        }
+    }
+
+    /// Ticks per second actually achieved since the last report
+    pub fn tps(&self) -> f64 {
+        let elapsed = self.since.elapsed().as_secs_f64();
+        if elapsed == 0.0 {
+            0.0
+        } else {
+            f64::from(self.ticks) / elapsed
+        }
+    }
+
+    /// Logs and resets the stats once enough ticks have been recorded
+    pub fn maybe_report(&mut self) {
+        if self.ticks < REPORT_INTERVAL {
+            return;
+        }
+
+        let lagging = self.overruns > 0 || self.skipped > 0;
+        log!(
+            if lagging { Level::Warn } else { Level::Debug },
+            "{:.1} TPS over the last {} ticks: mean {:?}, longest {:?}, {} overran, {} skipped",
+            self.tps(),
+            self.ticks,
+            self.mean(),
+            self.longest,
+            self.overruns,
+            self.skipped
+        );
+
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats() {
+        let mut stats = TickStats::new();
+        assert_eq!(stats.mean(), Duration::default());
+
+        stats.record(Duration::from_millis(10));
+        stats.record(Duration::from_millis(20));
+        stats.record(Duration::from_millis(60));
+
+        assert_eq!(stats.ticks, 3);
+        assert_eq!(stats.longest, Duration::from_millis(60));
+        assert_eq!(stats.overruns, 1);
+        assert_eq!(stats.mean(), Duration::from_millis(30));
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::world::ChunkPos;
+
+/// Smallest view distance a client can request
+pub const MIN_VIEW_DISTANCE: i32 = 2;
+
+/// Chunks a client has been sent around the centre of its view
+pub struct ClientView {
+    center: ChunkPos,
+    /// Radius in chunks, the smaller of the client's and the server's view distance
+    distance: i32,
+    /// Including those queued but not sent yet
+    loaded: HashSet<ChunkPos>,
+    /// Shared with the task sending the chunks
+    queued: Arc<Mutex<QueuedChunks>>,
+}
+
+/// Chunks queued to be sent that haven't been started yet
+#[derive(Default)]
+struct QueuedChunks {
+    /// Bumped on every update, replacing the queue of the previous one
+    generation: u64,
+    positions: HashSet<ChunkPos>,
+}
+
+/// Chunks that left and entered the view when it changed
+#[derive(Debug, Default)]
+pub struct ViewChange {
+    pub unload: Vec<ChunkPos>,
+    /// Nearest to the centre first
+    pub load: Vec<ChunkPos>,
+    generation: u64,
+}
+
+impl ClientView {
+    /// Empty view, nothing is loaded until the first update
+    pub fn new(distance: i32) -> Self {
+        Self {
+            center: ChunkPos::new(0, 0),
+            distance,
+            loaded: HashSet::new(),
+            queued: Arc::default(),
+        }
+    }
+
+    pub fn center(&self) -> ChunkPos {
+        self.center
+    }
+
+    pub fn distance(&self) -> i32 {
+        self.distance
+    }
+
+    /// Moves and/or resizes the view, returning the chunks that should be unloaded and loaded.
+    /// Chunks still queued from earlier updates are queued again in this one's order, so they're
+    /// never unloaded and the nearest are still sent first
+    pub fn update(&mut self, center: ChunkPos, distance: i32) -> ViewChange {
+        self.center = center;
+        self.distance = distance;
+
+        let queued = self.queued.clone();
+        let mut queued = queued.lock().unwrap_or_else(PoisonError::into_inner);
+        queued.generation += 1;
+        for pos in queued.positions.drain() {
+            self.loaded.remove(&pos);
+        }
+
+        let unload: Vec<_> = self
+            .loaded
+            .iter()
+            .filter(|pos| pos.distance(center) > distance)
+            .copied()
+            .collect();
+        for pos in &unload {
+            self.loaded.remove(pos);
+        }
+
+        let mut load: Vec<_> = center
+            .surrounding(distance)
+            .filter(|pos| self.loaded.insert(*pos))
+            .collect();
+        load.sort_by_key(|pos| {
+            let (dx, dz) = (pos.x - center.x, pos.z - center.z);
+            dx * dx + dz * dz
+        });
+
+        queued.positions = load.iter().copied().collect();
+        ViewChange {
+            unload,
+            load,
+            generation: queued.generation,
+        }
+    }
+
+    /// Checked before starting each chunk of the change, marking it as sent. False once the chunk
+    /// has left the view or been queued again by a later update
+    pub fn still_queued(&self, change: &ViewChange) -> impl FnMut(ChunkPos) -> bool + Send {
+        let queued = self.queued.clone();
+        let generation = change.generation;
+        move |pos| {
+            let mut queued = queued.lock().unwrap_or_else(PoisonError::into_inner);
+            queued.generation == generation && queued.positions.remove(&pos)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Updates the view as if all of the change was sent before the next one
+    fn update(view: &mut ClientView, center: ChunkPos, distance: i32) -> ViewChange {
+        let change = view.update(center, distance);
+        let mut still_queued = view.still_queued(&change);
+        assert!(change.load.iter().all(|pos| still_queued(*pos)));
+        change
+    }
+
+    #[test]
+    fn moving_and_resizing() {
+        let mut view = ClientView::new(2);
+        let change = update(&mut view, ChunkPos::new(0, 0), 2);
+        assert!(change.unload.is_empty());
+        assert_eq!(change.load.len(), 25);
+        assert_eq!(change.load[0], ChunkPos::new(0, 0));
+        assert_eq!(change.load[24].distance(ChunkPos::new(0, 0)), 2);
+
+        // one column in, one out
+        let mut change = update(&mut view, ChunkPos::new(1, 0), 2);
+        change.unload.sort_by_key(|pos| pos.z);
+        assert_eq!(
+            change.unload,
+            (-2..=2).map(|z| ChunkPos::new(-2, z)).collect::<Vec<_>>()
+        );
+        assert_eq!(change.load.len(), 5);
+        assert_eq!(change.load[0], ChunkPos::new(3, 0));
+        assert!(change.load.iter().all(|pos| pos.x == 3));
+
+        // nothing changes
+        let change = update(&mut view, ChunkPos::new(1, 0), 2);
+        assert!(change.unload.is_empty() && change.load.is_empty());
+
+        let change = update(&mut view, ChunkPos::new(1, 0), 1);
+        assert_eq!(change.unload.len(), 16);
+        assert!(change.load.is_empty());
+
+        let change = update(&mut view, ChunkPos::new(1, 0), 3);
+        assert_eq!(change.load.len(), 49 - 9);
+        assert_eq!(change.load[0].distance(ChunkPos::new(1, 0)), 2);
+    }
+
+    #[test]
+    fn requeues_unsent_chunks() {
+        let mut view = ClientView::new(1);
+        let first = view.update(ChunkPos::new(0, 0), 1);
+        let mut still_queued = view.still_queued(&first);
+        // only the centre and the two nearest after it are sent
+        for pos in &first.load[..3] {
+            assert!(still_queued(*pos));
+        }
+        assert_eq!(
+            &first.load[1..3],
+            &[ChunkPos::new(-1, 0), ChunkPos::new(0, -1)]
+        );
+
+        let second = view.update(ChunkPos::new(1, 0), 1);
+        assert!(first.load[3..].iter().all(|pos| !still_queued(*pos)));
+        assert_eq!(second.unload, vec![ChunkPos::new(-1, 0)]);
+        assert_eq!(second.load.len(), 7);
+        assert_eq!(second.load[0], ChunkPos::new(1, 0));
+
+        let mut still_queued = view.still_queued(&second);
+        assert!(second.load.iter().all(|pos| still_queued(*pos)));
+    }
+}
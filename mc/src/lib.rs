@@ -1,10 +1,14 @@
 pub mod connection;
 
+pub mod access;
+pub mod chat;
 pub mod config;
 pub mod error;
 pub mod game;
+pub mod nbt;
 pub mod packet;
 pub mod server;
+pub mod world;
 
 pub(crate) mod prelude {
     pub use async_std::io::prelude::*;
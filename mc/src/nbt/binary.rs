@@ -0,0 +1,228 @@
+//! Big-endian binary encoding, as used in files and packets
+
+use crate::error::{McError, McResult};
+use crate::nbt::Tag;
+
+const TAG_END: u8 = 0;
+
+/// Deeper nesting is rejected rather than risking a stack overflow
+const MAX_DEPTH: usize = 512;
+
+/// Reads a big-endian number of the given type
+macro_rules! read_be {
+    ($buf:expr, $ty:ty) => {{
+        let mut bytes = [0u8; std::mem::size_of::<$ty>()];
+        bytes.copy_from_slice(take($buf, std::mem::size_of::<$ty>())?);
+        <$ty>::from_be_bytes(bytes)
+    }};
+}
+
+impl Tag {
+    /// Reads a named root tag, advancing the slice past it
+    pub fn read_named(buf: &mut &[u8]) -> McResult<(String, Tag)> {
+        let id = read_u8(buf)?;
+        if id == TAG_END {
+            return Err(McError::BadNbt("unexpected end tag".to_owned()));
+        }
+
+        let name = read_string(buf)?;
+        let tag = Tag::read_payload(id, buf, 0)?;
+        Ok((name, tag))
+    }
+
+    fn read_payload(id: u8, buf: &mut &[u8], depth: usize) -> McResult<Tag> {
+        if depth > MAX_DEPTH {
+            return Err(McError::BadNbt("nested too deeply".to_owned()));
+        }
+
+        let tag = match id {
+            1 => Tag::Byte(read_u8(buf)? as i8),
+            2 => Tag::Short(read_be!(buf, i16)),
+            3 => Tag::Int(read_be!(buf, i32)),
+            4 => Tag::Long(read_be!(buf, i64)),
+            5 => Tag::Float(read_be!(buf, f32)),
+            6 => Tag::Double(read_be!(buf, f64)),
+            7 => {
+                let len = read_length(buf, 1)?;
+                let bytes = take(buf, len)?;
+                Tag::ByteArray(bytes.iter().map(|b| *b as i8).collect())
+            }
+            8 => Tag::String(read_string(buf)?),
+            9 => {
+                let element_id = read_u8(buf)?;
+                let len = read_length(buf, 1)?;
+                if element_id == TAG_END && len > 0 {
+                    return Err(McError::BadNbt("non-empty list of end tags".to_owned()));
+                }
+
+                let mut tags = Vec::with_capacity(len);
+                for _ in 0..len {
+                    tags.push(Tag::read_payload(element_id, buf, depth + 1)?);
+                }
+                Tag::List(tags)
+            }
+            10 => {
+                let mut entries = Vec::new();
+                loop {
+                    let id = read_u8(buf)?;
+                    if id == TAG_END {
+                        break;
+                    }
+
+                    let name = read_string(buf)?;
+                    entries.push((name, Tag::read_payload(id, buf, depth + 1)?));
+                }
+                Tag::Compound(entries)
+            }
+            11 => {
+                let len = read_length(buf, 4)?;
+                let mut ints = Vec::with_capacity(len);
+                for _ in 0..len {
+                    ints.push(read_be!(buf, i32));
+                }
+                Tag::IntArray(ints)
+            }
+            12 => {
+                let len = read_length(buf, 8)?;
+                let mut longs = Vec::with_capacity(len);
+                for _ in 0..len {
+                    longs.push(read_be!(buf, i64));
+                }
+                Tag::LongArray(longs)
+            }
+            id => return Err(McError::BadNbt(format!("unknown tag type {}", id))),
+        };
+
+        Ok(tag)
+    }
+
+    /// Writes as a named root tag
+    pub fn write_named(&self, name: &str, buf: &mut Vec<u8>) {
+        buf.push(self.id());
+        write_string(name, buf);
+        self.write_payload(buf);
+    }
+
+    fn write_payload(&self, buf: &mut Vec<u8>) {
+        match self {
+            Tag::Byte(b) => buf.push(*b as u8),
+            Tag::Short(s) => buf.extend_from_slice(&s.to_be_bytes()),
+            Tag::Int(i) => buf.extend_from_slice(&i.to_be_bytes()),
+            Tag::Long(l) => buf.extend_from_slice(&l.to_be_bytes()),
+            Tag::Float(f) => buf.extend_from_slice(&f.to_be_bytes()),
+            Tag::Double(d) => buf.extend_from_slice(&d.to_be_bytes()),
+            Tag::ByteArray(bytes) => {
+                buf.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+                buf.extend(bytes.iter().map(|b| *b as u8));
+            }
+            Tag::String(s) => write_string(s, buf),
+            Tag::List(tags) => {
+                // empty lists are typed as End
+                buf.push(tags.first().map(Tag::id).unwrap_or(TAG_END));
+                buf.extend_from_slice(&(tags.len() as i32).to_be_bytes());
+                for tag in tags {
+                    tag.write_payload(buf);
+                }
+            }
+            Tag::Compound(entries) => {
+                for (name, tag) in entries {
+                    tag.write_named(name, buf);
+                }
+                buf.push(TAG_END);
+            }
+            Tag::IntArray(ints) => {
+                buf.extend_from_slice(&(ints.len() as i32).to_be_bytes());
+                for i in ints {
+                    buf.extend_from_slice(&i.to_be_bytes());
+                }
+            }
+            Tag::LongArray(longs) => {
+                buf.extend_from_slice(&(longs.len() as i32).to_be_bytes());
+                for l in longs {
+                    buf.extend_from_slice(&l.to_be_bytes());
+                }
+            }
+        }
+    }
+}
+
+/// Java's modified UTF-8, where nulls take two bytes and supplementary characters are written as
+/// surrogate pairs. Strings too long for the length prefix are truncated after the last character
+/// that fits
+fn write_string(s: &str, buf: &mut Vec<u8>) {
+    let mut bytes = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        let mut encoded = [0u8; 6];
+        let encoded: &[u8] = match c {
+            '\0' => &[0xc0, 0x80],
+            c if c.len_utf8() == 4 => {
+                let mut units = [0u16; 2];
+                for (i, unit) in c.encode_utf16(&mut units).iter().enumerate() {
+                    encoded[i * 3..i * 3 + 3].copy_from_slice(&[
+                        0xe0 | (unit >> 12) as u8,
+                        0x80 | ((unit >> 6) & 0x3f) as u8,
+                        0x80 | (unit & 0x3f) as u8,
+                    ]);
+                }
+                &encoded
+            }
+            c => c.encode_utf8(&mut encoded).as_bytes(),
+        };
+
+        if bytes.len() + encoded.len() > u16::MAX as usize {
+            break;
+        }
+        bytes.extend_from_slice(encoded);
+    }
+
+    buf.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&bytes);
+}
+
+fn take<'a>(buf: &mut &'a [u8], n: usize) -> McResult<&'a [u8]> {
+    if buf.len() < n {
+        return Err(McError::BadNbt("unexpected end of data".to_owned()));
+    }
+
+    let (bytes, rest) = buf.split_at(n);
+    *buf = rest;
+    Ok(bytes)
+}
+
+fn read_u8(buf: &mut &[u8]) -> McResult<u8> {
+    Ok(take(buf, 1)?[0])
+}
+
+/// Array or list length, checked against the remaining data so a corrupt length can't cause a
+/// huge allocation
+fn read_length(buf: &mut &[u8], element_size: usize) -> McResult<usize> {
+    let len = read_be!(buf, i32);
+    if len < 0 || len as usize * element_size > buf.len() {
+        return Err(McError::BadNbt(format!("invalid length {}", len)));
+    }
+
+    Ok(len as usize)
+}
+
+fn read_string(buf: &mut &[u8]) -> McResult<String> {
+    let len = read_be!(buf, u16) as usize;
+    let bytes = take(buf, len)?;
+    if let Ok(s) = std::str::from_utf8(bytes) {
+        // only differs from modified UTF-8 by nulls, and surrogates aren't valid UTF-8
+        return Ok(s.to_owned());
+    }
+
+    let mut units = Vec::with_capacity(len);
+    let mut bytes = bytes.iter().copied();
+    while let Some(b) = bytes.next() {
+        let mut continuation = || bytes.next().map(|b| (b & 0x3f) as u16).unwrap_or(0);
+        let unit = match b {
+            0x00..=0x7f => b as u16,
+            0xc0..=0xdf => ((b & 0x1f) as u16) << 6 | continuation(),
+            0xe0..=0xef => ((b & 0x0f) as u16) << 12 | continuation() << 6 | continuation(),
+            _ => std::char::REPLACEMENT_CHARACTER as u16,
+        };
+        units.push(unit);
+    }
+    Ok(String::from_utf16_lossy(&units))
+}
@@ -0,0 +1,42 @@
+//! Compressed root tags, gzip for files like `level.dat` and zlib for region file chunks
+
+use std::io::{Read, Write};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+
+use crate::error::McResult;
+use crate::nbt::Tag;
+
+pub fn read_gzip(data: &[u8]) -> McResult<(String, Tag)> {
+    read_compressed(GzDecoder::new(data))
+}
+
+pub fn read_zlib(data: &[u8]) -> McResult<(String, Tag)> {
+    read_compressed(ZlibDecoder::new(data))
+}
+
+pub fn write_gzip(name: &str, tag: &Tag) -> McResult<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&uncompressed(name, tag))?;
+    Ok(encoder.finish()?)
+}
+
+pub fn write_zlib(name: &str, tag: &Tag) -> McResult<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&uncompressed(name, tag))?;
+    Ok(encoder.finish()?)
+}
+
+fn read_compressed(mut decoder: impl Read) -> McResult<(String, Tag)> {
+    let mut data = Vec::new();
+    decoder.read_to_end(&mut data)?;
+    Tag::read_named(&mut data.as_slice())
+}
+
+fn uncompressed(name: &str, tag: &Tag) -> Vec<u8> {
+    let mut data = Vec::new();
+    tag.write_named(name, &mut data);
+    data
+}
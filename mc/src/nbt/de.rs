@@ -0,0 +1,191 @@
+//! Serde deserializer reading from a tag tree
+
+use std::fmt::Display;
+
+use serde::de::value::{MapDeserializer, SeqDeserializer, StrDeserializer};
+use serde::de::{
+    self, Deserialize, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, VariantAccess,
+    Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+use crate::error::{McError, McResult};
+use crate::nbt::Tag;
+
+/// The reverse of [`to_tag`](super::to_tag). Numbers are widened where needed, and arrays can be
+/// read as lists
+pub fn from_tag<'de, T: Deserialize<'de>>(tag: &'de Tag) -> McResult<T> {
+    T::deserialize(TagDeserializer(tag))
+}
+
+impl de::Error for McError {
+    fn custom<T: Display>(msg: T) -> Self {
+        McError::BadNbt(msg.to_string())
+    }
+}
+
+#[derive(Copy, Clone)]
+struct TagDeserializer<'de>(&'de Tag);
+
+/// Single entry compound holding an enum variant's contents
+struct VariantDeserializer<'de> {
+    variant: &'de str,
+    value: &'de Tag,
+}
+
+impl<'de> IntoDeserializer<'de, McError> for TagDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Unsigned integers from the signed tag of the same width, as written by the serializer
+macro_rules! deserialize_unsigned {
+    ($method:ident, $visit:ident, $tag:ident, $ty:ty) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> McResult<V::Value> {
+            match self.0 {
+                Tag::$tag(v) => visitor.$visit(*v as $ty),
+                _ => self.deserialize_any(visitor),
+            }
+        }
+    };
+}
+
+impl<'de> Deserializer<'de> for TagDeserializer<'de> {
+    type Error = McError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> McResult<V::Value> {
+        match self.0 {
+            Tag::Byte(b) => visitor.visit_i8(*b),
+            Tag::Short(s) => visitor.visit_i16(*s),
+            Tag::Int(i) => visitor.visit_i32(*i),
+            Tag::Long(l) => visitor.visit_i64(*l),
+            Tag::Float(f) => visitor.visit_f32(*f),
+            Tag::Double(d) => visitor.visit_f64(*d),
+            Tag::ByteArray(bytes) => visitor.visit_seq(SeqDeserializer::new(bytes.iter().copied())),
+            Tag::String(s) => visitor.visit_borrowed_str(s),
+            Tag::List(tags) => {
+                visitor.visit_seq(SeqDeserializer::new(tags.iter().map(TagDeserializer)))
+            }
+            Tag::Compound(entries) => visitor.visit_map(MapDeserializer::new(
+                entries
+                    .iter()
+                    .map(|(name, tag)| (name.as_str(), TagDeserializer(tag))),
+            )),
+            Tag::IntArray(ints) => visitor.visit_seq(SeqDeserializer::new(ints.iter().copied())),
+            Tag::LongArray(longs) => visitor.visit_seq(SeqDeserializer::new(longs.iter().copied())),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> McResult<V::Value> {
+        match self.0 {
+            Tag::Byte(b) => visitor.visit_bool(*b != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_unsigned!(deserialize_u8, visit_u8, Byte, u8);
+    deserialize_unsigned!(deserialize_u16, visit_u16, Short, u16);
+    deserialize_unsigned!(deserialize_u32, visit_u32, Int, u32);
+    deserialize_unsigned!(deserialize_u64, visit_u64, Long, u64);
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> McResult<V::Value> {
+        match self.0 {
+            Tag::ByteArray(bytes) => {
+                visitor.visit_byte_buf(bytes.iter().map(|b| *b as u8).collect())
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> McResult<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    /// Missing values are left out of compounds, so anything present is `Some`
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> McResult<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> McResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> McResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> McResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> McResult<V::Value> {
+        match self.0 {
+            Tag::String(s) => visitor.visit_enum(s.as_str().into_deserializer()),
+            Tag::Compound(entries) if entries.len() == 1 => {
+                let (variant, value) = &entries[0];
+                visitor.visit_enum(VariantDeserializer { variant, value })
+            }
+            _ => Err(McError::BadNbt(
+                "expected a string or single entry compound for an enum".to_owned(),
+            )),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u128 f32 f64 char str string seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+impl<'de> EnumAccess<'de> for VariantDeserializer<'de> {
+    type Error = McError;
+    type Variant = TagDeserializer<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> McResult<(V::Value, TagDeserializer<'de>)> {
+        let variant: StrDeserializer<McError> = self.variant.into_deserializer();
+        Ok((seed.deserialize(variant)?, TagDeserializer(self.value)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for TagDeserializer<'de> {
+    type Error = McError;
+
+    fn unit_variant(self) -> McResult<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> McResult<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> McResult<V::Value> {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> McResult<V::Value> {
+        self.deserialize_any(visitor)
+    }
+}
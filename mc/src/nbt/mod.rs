@@ -0,0 +1,326 @@
+//! Named Binary Tag format, with serde support so game structs can be converted to and from tags
+
+use serde::Deserialize;
+
+pub use compression::{read_gzip, read_zlib, write_gzip, write_zlib};
+pub use de::from_tag;
+pub use ser::to_tag;
+
+mod binary;
+mod compression;
+mod de;
+mod ser;
+mod snbt;
+
+/// Compound entries keep their insertion order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(Vec<(String, Tag)>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+/// Serialized as a byte array tag rather than a list of bytes
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct ByteArray(pub Vec<i8>);
+
+/// Serialized as an int array tag rather than a list of ints
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct IntArray(pub Vec<i32>);
+
+/// Serialized as a long array tag rather than a list of longs
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct LongArray(pub Vec<i64>);
+
+impl Tag {
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// Entry in a compound
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(entries) => entries.iter().find(|(n, _)| n == name).map(|(_, t)| t),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Tag> {
+        match self {
+            Tag::Compound(entries) => entries.iter_mut().find(|(n, _)| n == name).map(|(_, t)| t),
+            _ => None,
+        }
+    }
+
+    /// Replaces an entry in a compound, appending it if it's new. No-op on other tags
+    pub fn insert(&mut self, name: &str, tag: Tag) {
+        if let Tag::Compound(entries) = self {
+            match entries.iter_mut().find(|(n, _)| n == name) {
+                Some((_, existing)) => *existing = tag,
+                None => entries.push((name.to_owned(), tag)),
+            }
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Tag> {
+        match self {
+            Tag::Compound(entries) => {
+                let idx = entries.iter().position(|(n, _)| n == name)?;
+                Some(entries.remove(idx).1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Bytes, shorts and ints widened
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            Tag::Byte(b) => Some(*b as i32),
+            Tag::Short(s) => Some(*s as i32),
+            Tag::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_long(&self) -> Option<i64> {
+        match self {
+            Tag::Long(l) => Some(*l),
+            tag => tag.as_int().map(i64::from),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(tags) => Some(tags),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&[(String, Tag)]> {
+        match self {
+            Tag::Compound(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    pub fn as_int_array(&self) -> Option<&[i32]> {
+        match self {
+            Tag::IntArray(ints) => Some(ints),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(longs) => Some(longs),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use quickcheck::{Arbitrary, Gen};
+    use quickcheck_macros::quickcheck;
+    use serde::Serialize;
+
+    use super::*;
+
+    /// Deeper tags only hold primitives
+    const ARBITRARY_DEPTH: usize = 3;
+
+    impl Arbitrary for Tag {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            arbitrary_tag(g, 0)
+        }
+    }
+
+    fn arbitrary_tag<G: Gen>(g: &mut G, depth: usize) -> Tag {
+        let ids: &[u8] = if depth < ARBITRARY_DEPTH {
+            &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]
+        } else {
+            &[1, 2, 3, 4, 5, 6, 7, 8, 11, 12]
+        };
+        let id = ids[usize::arbitrary(g) % ids.len()];
+        arbitrary_of_type(g, id, depth)
+    }
+
+    fn arbitrary_of_type<G: Gen>(g: &mut G, id: u8, depth: usize) -> Tag {
+        let len = usize::arbitrary(g) % 8;
+        match id {
+            1 => Tag::Byte(Arbitrary::arbitrary(g)),
+            2 => Tag::Short(Arbitrary::arbitrary(g)),
+            3 => Tag::Int(Arbitrary::arbitrary(g)),
+            4 => Tag::Long(Arbitrary::arbitrary(g)),
+            // from integers to avoid NaN, which isn't equal to itself
+            5 => Tag::Float(i32::arbitrary(g) as f32 / 8.0),
+            6 => Tag::Double(i64::arbitrary(g) as f64 / 8.0),
+            7 => Tag::ByteArray(Arbitrary::arbitrary(g)),
+            8 => Tag::String(Arbitrary::arbitrary(g)),
+            9 => {
+                let element = arbitrary_tag(g, depth + 1).id();
+                Tag::List(
+                    (0..len)
+                        .map(|_| arbitrary_of_type(g, element, depth + 1))
+                        .collect(),
+                )
+            }
+            10 => Tag::Compound(
+                (0..len)
+                    .map(|_| (String::arbitrary(g), arbitrary_tag(g, depth + 1)))
+                    .collect(),
+            ),
+            11 => Tag::IntArray(Arbitrary::arbitrary(g)),
+            _ => Tag::LongArray(Arbitrary::arbitrary(g)),
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Item {
+        id: String,
+        count: u8,
+        damage: Option<i16>,
+        unbreakable: bool,
+        lore: Vec<String>,
+        heights: LongArray,
+        attributes: BTreeMap<String, i32>,
+        kind: ItemKind,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    enum ItemKind {
+        Block,
+        Food(i32),
+        Tool { durability: u32 },
+    }
+
+    impl Arbitrary for Item {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let (id, count, damage, unbreakable, lore) = Arbitrary::arbitrary(g);
+            let kind = match u8::arbitrary(g) % 3 {
+                0 => ItemKind::Block,
+                1 => ItemKind::Food(Arbitrary::arbitrary(g)),
+                _ => ItemKind::Tool {
+                    durability: Arbitrary::arbitrary(g),
+                },
+            };
+
+            Item {
+                id,
+                count,
+                damage,
+                unbreakable,
+                lore,
+                heights: LongArray(Arbitrary::arbitrary(g)),
+                attributes: Arbitrary::arbitrary(g),
+                kind,
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn binary_round_trip(name: String, tag: Tag) -> bool {
+        let mut buf = Vec::new();
+        tag.write_named(&name, &mut buf);
+        let mut slice = buf.as_slice();
+        Tag::read_named(&mut slice).ok() == Some((name, tag)) && slice.is_empty()
+    }
+
+    #[quickcheck]
+    fn compressed_round_trip(tag: Tag) -> bool {
+        let gzip = write_gzip("gzip", &tag).unwrap();
+        let zlib = write_zlib("zlib", &tag).unwrap();
+        read_gzip(&gzip).ok() == Some(("gzip".to_owned(), tag.clone()))
+            && read_zlib(&zlib).ok() == Some(("zlib".to_owned(), tag))
+    }
+
+    #[quickcheck]
+    fn serde_round_trip(item: Item) -> bool {
+        // through the binary format too, to check the tag is valid
+        let tag = to_tag(&item).unwrap();
+        let mut buf = Vec::new();
+        tag.write_named("", &mut buf);
+        let (_, read) = Tag::read_named(&mut buf.as_slice()).unwrap();
+
+        from_tag::<Item>(&read).ok() == Some(item)
+    }
+
+    #[test]
+    fn truncates_long_strings() {
+        let tag = Tag::String("é".repeat(40000));
+        let mut buf = Vec::new();
+        tag.write_named("", &mut buf);
+        let (_, read) = Tag::read_named(&mut buf.as_slice()).unwrap();
+        assert_eq!(read, Tag::String("é".repeat(32767)));
+    }
+
+    #[test]
+    fn serde_tag_types() {
+        let item = Item {
+            id: "minecraft:stone".to_owned(),
+            count: 200,
+            damage: None,
+            unbreakable: true,
+            lore: Vec::new(),
+            heights: LongArray(vec![1, 2]),
+            attributes: BTreeMap::new(),
+            kind: ItemKind::Tool { durability: 5 },
+        };
+
+        assert_eq!(
+            to_tag(&item).unwrap(),
+            Tag::Compound(vec![
+                ("Id".to_owned(), Tag::String("minecraft:stone".to_owned())),
+                ("Count".to_owned(), Tag::Byte(-56)),
+                ("Unbreakable".to_owned(), Tag::Byte(1)),
+                ("Lore".to_owned(), Tag::List(Vec::new())),
+                ("Heights".to_owned(), Tag::LongArray(vec![1, 2])),
+                ("Attributes".to_owned(), Tag::Compound(Vec::new())),
+                (
+                    "Kind".to_owned(),
+                    Tag::Compound(vec![(
+                        "Tool".to_owned(),
+                        Tag::Compound(vec![("durability".to_owned(), Tag::Int(5))])
+                    )])
+                ),
+            ])
+        );
+
+        assert!(to_tag(&vec![Some(1), None]).is_err());
+    }
+}
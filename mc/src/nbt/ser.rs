@@ -0,0 +1,411 @@
+//! Serde serializer producing a tag tree
+
+use std::fmt::Display;
+
+use serde::ser::{self, Serialize};
+
+use crate::error::{McError, McResult};
+use crate::nbt::{ByteArray, IntArray, LongArray, Tag};
+
+/// Newtype struct names marking sequences to be written as arrays rather than lists
+pub(super) const BYTE_ARRAY: &str = "__nbt_byte_array";
+pub(super) const INT_ARRAY: &str = "__nbt_int_array";
+pub(super) const LONG_ARRAY: &str = "__nbt_long_array";
+
+/// Structs and maps become compounds, leaving out `None` fields, and sequences become lists.
+/// Unsigned integers are stored in the signed tag of the same width
+pub fn to_tag<T: Serialize + ?Sized>(value: &T) -> McResult<Tag> {
+    value
+        .serialize(TagSerializer)?
+        .ok_or_else(|| McError::BadNbt("can't serialize a missing value".to_owned()))
+}
+
+impl ser::Error for McError {
+    fn custom<T: Display>(msg: T) -> Self {
+        McError::BadNbt(msg.to_string())
+    }
+}
+
+impl Serialize for ByteArray {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(BYTE_ARRAY, &self.0)
+    }
+}
+
+impl Serialize for IntArray {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(INT_ARRAY, &self.0)
+    }
+}
+
+impl Serialize for LongArray {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(LONG_ARRAY, &self.0)
+    }
+}
+
+struct TagSerializer;
+
+struct ListSerializer {
+    tags: Vec<Tag>,
+    /// Enum variant to wrap the list in
+    variant: Option<&'static str>,
+}
+
+struct CompoundSerializer {
+    entries: Vec<(String, Tag)>,
+    /// Key of a map entry whose value is still to come
+    key: Option<String>,
+    /// Enum variant to wrap the compound in
+    variant: Option<&'static str>,
+}
+
+impl ser::Serializer for TagSerializer {
+    /// None for missing optional values
+    type Ok = Option<Tag>;
+    type Error = McError;
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = ListSerializer;
+    type SerializeMap = CompoundSerializer;
+    type SerializeStruct = CompoundSerializer;
+    type SerializeStructVariant = CompoundSerializer;
+
+    fn serialize_bool(self, v: bool) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Byte(v as i8)))
+    }
+
+    fn serialize_i8(self, v: i8) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Byte(v)))
+    }
+
+    fn serialize_i16(self, v: i16) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Short(v)))
+    }
+
+    fn serialize_i32(self, v: i32) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Int(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Long(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Byte(v as i8)))
+    }
+
+    fn serialize_u16(self, v: u16) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Short(v as i16)))
+    }
+
+    fn serialize_u32(self, v: u32) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Int(v as i32)))
+    }
+
+    fn serialize_u64(self, v: u64) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Long(v as i64)))
+    }
+
+    fn serialize_f32(self, v: f32) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Float(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Double(v)))
+    }
+
+    fn serialize_char(self, v: char) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::String(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::String(v.to_owned())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::ByteArray(v.iter().map(|b| *b as i8).collect())))
+    }
+
+    fn serialize_none(self) -> McResult<Option<Tag>> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> McResult<Option<Tag>> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> McResult<Option<Tag>> {
+        Ok(Some(Tag::Compound(Vec::new())))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> McResult<Option<Tag>> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> McResult<Option<Tag>> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> McResult<Option<Tag>> {
+        let tag = value.serialize(self)?;
+        match (name, tag) {
+            (BYTE_ARRAY, Some(Tag::List(tags))) => array(tags, |tag| match tag {
+                Tag::Byte(b) => Some(b),
+                _ => None,
+            })
+            .map(|bytes| Some(Tag::ByteArray(bytes))),
+            (INT_ARRAY, Some(Tag::List(tags))) => {
+                array(tags, |tag| tag.as_int()).map(|ints| Some(Tag::IntArray(ints)))
+            }
+            (LONG_ARRAY, Some(Tag::List(tags))) => {
+                array(tags, |tag| tag.as_long()).map(|longs| Some(Tag::LongArray(longs)))
+            }
+            (_, tag) => Ok(tag),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> McResult<Option<Tag>> {
+        let entries = value
+            .serialize(self)?
+            .map(|tag| (variant.to_owned(), tag))
+            .into_iter()
+            .collect();
+        Ok(Some(Tag::Compound(entries)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> McResult<ListSerializer> {
+        Ok(ListSerializer {
+            tags: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> McResult<ListSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> McResult<ListSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> McResult<ListSerializer> {
+        Ok(ListSerializer {
+            tags: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> McResult<CompoundSerializer> {
+        Ok(CompoundSerializer {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> McResult<CompoundSerializer> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> McResult<CompoundSerializer> {
+        Ok(CompoundSerializer {
+            entries: Vec::with_capacity(len),
+            key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+/// Elements of a list of numbers
+fn array<T>(tags: Vec<Tag>, element: impl Fn(Tag) -> Option<T>) -> McResult<Vec<T>> {
+    tags.into_iter()
+        .map(|tag| element(tag).ok_or_else(|| McError::BadNbt("invalid array element".to_owned())))
+        .collect()
+}
+
+/// Wraps a variant's contents in a compound named after it
+fn wrap_variant(tag: Tag, variant: Option<&'static str>) -> Option<Tag> {
+    Some(match variant {
+        Some(variant) => Tag::Compound(vec![(variant.to_owned(), tag)]),
+        None => tag,
+    })
+}
+
+impl ListSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> McResult<()> {
+        let tag = value
+            .serialize(TagSerializer)?
+            .ok_or_else(|| McError::BadNbt("lists can't hold missing values".to_owned()))?;
+
+        if let Some(first) = self.tags.first() {
+            if first.id() != tag.id() {
+                return Err(McError::BadNbt(
+                    "list elements must all be the same type".to_owned(),
+                ));
+            }
+        }
+
+        self.tags.push(tag);
+        Ok(())
+    }
+
+    fn finish(self) -> McResult<Option<Tag>> {
+        Ok(wrap_variant(Tag::List(self.tags), self.variant))
+    }
+}
+
+impl ser::SerializeSeq for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = McError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> McResult<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> McResult<Option<Tag>> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = McError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> McResult<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> McResult<Option<Tag>> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = McError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> McResult<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> McResult<Option<Tag>> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = McError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> McResult<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> McResult<Option<Tag>> {
+        self.finish()
+    }
+}
+
+impl CompoundSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> McResult<()> {
+        if let Some(tag) = value.serialize(TagSerializer)? {
+            self.entries.push((key, tag));
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> McResult<Option<Tag>> {
+        Ok(wrap_variant(Tag::Compound(self.entries), self.variant))
+    }
+}
+
+impl ser::SerializeMap for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = McError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> McResult<()> {
+        let key = match key.serialize(TagSerializer)? {
+            Some(Tag::String(s)) => s,
+            Some(tag) if tag.as_long().is_some() => tag.to_string(),
+            _ => return Err(McError::BadNbt("map keys must be strings".to_owned())),
+        };
+
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> McResult<()> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| McError::BadNbt("map value without a key".to_owned()))?;
+        self.push(key, value)
+    }
+
+    fn end(self) -> McResult<Option<Tag>> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = McError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> McResult<()> {
+        self.push(key.to_owned(), value)
+    }
+
+    fn end(self) -> McResult<Option<Tag>> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = McError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> McResult<()> {
+        self.push(key.to_owned(), value)
+    }
+
+    fn end(self) -> McResult<Option<Tag>> {
+        self.finish()
+    }
+}
@@ -0,0 +1,92 @@
+//! Stringified NBT, the text form used in commands, for debugging
+
+use std::fmt::{self, Display, Formatter, Write};
+
+use crate::nbt::Tag;
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Tag::Byte(b) => write!(f, "{}b", b),
+            Tag::Short(s) => write!(f, "{}s", s),
+            Tag::Int(i) => write!(f, "{}", i),
+            Tag::Long(l) => write!(f, "{}L", l),
+            Tag::Float(x) => write!(f, "{}f", x),
+            Tag::Double(x) => write!(f, "{}d", x),
+            Tag::ByteArray(bytes) => write_array(f, "B;", bytes.iter().map(|b| Tag::Byte(*b))),
+            Tag::String(s) => write_quoted(f, s),
+            Tag::List(tags) => write_array(f, "", tags.iter()),
+            Tag::Compound(entries) => {
+                f.write_char('{')?;
+                for (i, (name, tag)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+
+                    if !name.is_empty() && name.chars().all(is_unquoted_char) {
+                        f.write_str(name)?;
+                    } else {
+                        write_quoted(f, name)?;
+                    }
+                    write!(f, ":{}", tag)?;
+                }
+                f.write_char('}')
+            }
+            Tag::IntArray(ints) => write_array(f, "I;", ints.iter().map(|i| Tag::Int(*i))),
+            Tag::LongArray(longs) => write_array(f, "L;", longs.iter().map(|l| Tag::Long(*l))),
+        }
+    }
+}
+
+/// Characters allowed in compound keys without quotes
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_-.+".contains(c)
+}
+
+fn write_quoted(f: &mut Formatter, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            f.write_char('\\')?;
+        }
+        f.write_char(c)?;
+    }
+    f.write_char('"')
+}
+
+fn write_array(
+    f: &mut Formatter,
+    prefix: &str,
+    items: impl Iterator<Item = impl Display>,
+) -> fmt::Result {
+    write!(f, "[{}", prefix)?;
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        write!(f, "{}", item)?;
+    }
+    f.write_char(']')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let tag = Tag::Compound(vec![
+            ("Count".to_owned(), Tag::Byte(64)),
+            ("id".to_owned(), Tag::String("minecraft:\"odd\"".to_owned())),
+            ("has space".to_owned(), Tag::List(vec![Tag::Double(0.5)])),
+            ("Pos".to_owned(), Tag::IntArray(vec![1, -2])),
+            ("Big".to_owned(), Tag::Long(1)),
+            ("Empty".to_owned(), Tag::Compound(Vec::new())),
+        ]);
+
+        assert_eq!(
+            tag.to_string(),
+            r#"{Count:64b,id:"minecraft:\"odd\"","has space":[0.5d],Pos:[I;1,-2],Big:1L,Empty:{}}"#
+        );
+    }
+}
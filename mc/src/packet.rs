@@ -1,25 +1,36 @@
 use minecraft_server_protocol::v1_15_2 as mc;
 
+use crate::chat::ChatPosition;
+use crate::connection::ClientBoundPacket;
 use crate::error::McError;
+use crate::nbt::Tag;
 pub use mc::*;
 use minecraft_server_protocol::types::StringField;
+use serde_json::Value;
 use std::borrow::Cow;
+use uuid::Uuid;
 
 pub trait DisconnectExt: Sized {
     fn with_error(error: &McError) -> Self;
 }
 
 pub trait KeepAliveExt: Sized {
-    fn generate() -> Self;
+    fn new(id: i64) -> Self;
 }
 
 pub trait PlayerPositionAndLookExt: Sized {
     fn new(pos: (f64, f64, f64), teleport_id: i32) -> Self;
 }
 
+pub trait ChatExt: Sized {
+    fn new(component: &Value, position: ChatPosition) -> Self;
+}
+
 fn disconnect_reason(error: &McError) -> StringField {
     let reason = if let McError::PleaseDisconnect = error {
         Cow::Borrowed("EOF")
+    } else if let McError::LoginDenied(reason) = error {
+        Cow::Borrowed(reason.as_str())
     } else {
         Cow::Owned(format!(
             "§cSHIT, AN ERROR OCCURRED!\n§fpls don't panic\n\n§7{}",
@@ -45,10 +56,9 @@ impl DisconnectExt for play::client::KickDisconnect {
 }
 
 impl KeepAliveExt for play::client::KeepAlive {
-    fn generate() -> Self {
-        let random = 4; // good enough
+    fn new(id: i64) -> Self {
         Self {
-            keep_alive_id: random.into(),
+            keep_alive_id: id.into(),
         }
     }
 }
@@ -66,3 +76,143 @@ impl PlayerPositionAndLookExt for play::client::Position {
         }
     }
 }
+
+impl ChatExt for play::client::Chat {
+    fn new(component: &Value, position: ChatPosition) -> Self {
+        Self {
+            message: StringField::new(component.to_string()),
+            position: (position as i8).into(),
+        }
+    }
+}
+
+/// Builder for packets that are encoded by hand, for those with fields that the generated
+/// bindings can't express (NBT, switches, arrays of structures)
+pub struct PacketBuilder {
+    id: i32,
+    body: Vec<u8>,
+}
+
+impl PacketBuilder {
+    pub fn new(id: i32) -> Self {
+        Self {
+            id,
+            body: Vec::with_capacity(64),
+        }
+    }
+
+    pub fn bool(&mut self, b: bool) -> &mut Self {
+        self.body.push(b as u8);
+        self
+    }
+
+    pub fn byte(&mut self, b: i8) -> &mut Self {
+        self.body.push(b as u8);
+        self
+    }
+
+    pub fn short(&mut self, s: i16) -> &mut Self {
+        self.bytes(&s.to_be_bytes())
+    }
+
+    pub fn int(&mut self, i: i32) -> &mut Self {
+        self.bytes(&i.to_be_bytes())
+    }
+
+    pub fn long(&mut self, l: i64) -> &mut Self {
+        self.bytes(&l.to_be_bytes())
+    }
+
+    pub fn float(&mut self, f: f32) -> &mut Self {
+        self.bytes(&f.to_be_bytes())
+    }
+
+    pub fn double(&mut self, d: f64) -> &mut Self {
+        self.bytes(&d.to_be_bytes())
+    }
+
+    pub fn var_int(&mut self, i: i32) -> &mut Self {
+        write_var_int(&mut self.body, i);
+        self
+    }
+
+    pub fn string(&mut self, s: &str) -> &mut Self {
+        self.var_int(s.len() as i32).bytes(s.as_bytes())
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.body.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn uuid(&mut self, uuid: &Uuid) -> &mut Self {
+        self.bytes(uuid.as_bytes())
+    }
+
+    /// Prefixed with VarInt length
+    pub fn byte_array(&mut self, bytes: &[u8]) -> &mut Self {
+        self.var_int(bytes.len() as i32).bytes(bytes)
+    }
+
+    /// Root compound with an empty name
+    pub fn nbt(&mut self, tag: &Tag) -> &mut Self {
+        tag.write_named("", &mut self.body);
+        self
+    }
+
+    pub fn build(&mut self) -> ClientBoundPacket {
+        ClientBoundPacket::raw(self.id, std::mem::take(&mut self.body))
+    }
+}
+
+pub fn write_var_int(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            break;
+        }
+
+        buf.push(byte | 0x80);
+    }
+}
+
+/// Returns value and the number of bytes it took up
+pub fn read_var_int(buf: &[u8]) -> Option<(i32, usize)> {
+    let mut value = 0u32;
+    for (i, byte) in buf.iter().take(5).enumerate() {
+        value |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value as i32, i + 1));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn var_int() {
+        for &(value, bytes) in &[
+            (0, &[0x00][..]),
+            (1, &[0x01]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (25565, &[0xdd, 0xc7, 0x01]),
+            (2_147_483_647, &[0xff, 0xff, 0xff, 0xff, 0x07]),
+            (-1, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+        ] {
+            let mut buf = Vec::new();
+            write_var_int(&mut buf, value);
+            assert_eq!(buf, bytes);
+            assert_eq!(read_var_int(bytes), Some((value, bytes.len())));
+        }
+
+        assert_eq!(read_var_int(&[0x80, 0x80]), None);
+    }
+}
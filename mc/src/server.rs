@@ -1,8 +1,19 @@
+use std::path::Path;
+
+use log::*;
 use openssl::pkey::Private;
 use openssl::rsa::{Padding, Rsa};
 
-use crate::config;
+use crate::access::AccessLists;
+use crate::config::ServerConfig;
 use crate::error::{McError, McResult};
+use crate::game::PlayerList;
+
+/// Used if no icon is configured or it can't be loaded
+const DEFAULT_ICON: &str = include_str!("../icon.png.base64");
+
+/// Whitelist and ban lists are kept next to the config, in the working directory
+const ACCESS_LISTS_DIR: &str = ".";
 
 // TODO don't need to wrap the whole struct in mutex?
 // pub type &ServerData = Arc<Mutex<ServerData>>;
@@ -14,18 +25,63 @@ pub enum OnlineStatus {
 
 pub struct ServerData {
     rsa_key: Rsa<Private>,
+    config: ServerConfig,
+    player_list: PlayerList,
+    access: AccessLists,
+
+    /// Data URI for the server list
+    favicon: String,
 }
 
 impl ServerData {
-    pub fn new() -> McResult<Self> {
+    pub fn new(config: ServerConfig) -> McResult<Self> {
+        let favicon = {
+            let b64 = config
+                .icon_path
+                .as_ref()
+                .and_then(|path| match load_icon(path) {
+                    Ok(b64) => Some(b64),
+                    Err(e) => {
+                        warn!("failed to load server icon {}: {}", path.display(), e);
+                        None
+                    }
+                });
+
+            format!(
+                "data:image/png;base64,{}",
+                b64.as_deref().unwrap_or_else(|| DEFAULT_ICON.trim())
+            )
+        };
+
         Ok(Self {
             // TODO only generate if online
             rsa_key: Rsa::generate(1024).map_err(McError::OpenSSL)?,
+            access: AccessLists::load(ACCESS_LISTS_DIR, config.whitelist)?,
+            config,
+            player_list: PlayerList::default(),
+            favicon,
         })
 
         // Ok(Arc::new(Mutex::new(data)))
     }
 
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    pub fn player_list(&self) -> &PlayerList {
+        &self.player_list
+    }
+
+    /// Whitelist and bans
+    pub fn access(&self) -> &AccessLists {
+        &self.access
+    }
+
+    pub fn favicon(&self) -> &str {
+        &self.favicon
+    }
+
     pub fn public_key(&self) -> McResult<Vec<u8>> {
         self.rsa_key.public_key_to_der().map_err(McError::OpenSSL)
     }
@@ -41,7 +97,7 @@ impl ServerData {
     }
 
     pub fn online_status(&self) -> McResult<OnlineStatus> {
-        if config::ONLINE_MODE {
+        if self.config.online_mode {
             Ok(OnlineStatus::Online {
                 public_key: self.public_key()?,
             })
@@ -65,3 +121,29 @@ impl ServerData {
     //     self.client_data.remove(&tid);
     // }
 }
+
+/// Reads a 64x64 PNG and base64 encodes it
+fn load_icon(path: &Path) -> McResult<String> {
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    let bytes = std::fs::read(path)?;
+    if !bytes.starts_with(PNG_SIGNATURE) || bytes.len() < 24 {
+        return Err(McError::BadIcon("not a PNG".to_owned()));
+    }
+
+    // IHDR is always the first chunk
+    let dimension = |offset: usize| {
+        let mut be = [0u8; 4];
+        be.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_be_bytes(be)
+    };
+    let (width, height) = (dimension(16), dimension(20));
+    if (width, height) != (64, 64) {
+        return Err(McError::BadIcon(format!(
+            "must be 64x64 but is {}x{}",
+            width, height
+        )));
+    }
+
+    Ok(openssl::base64::encode_block(&bytes))
+}
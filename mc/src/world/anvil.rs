@@ -0,0 +1,358 @@
+//! Conversion of chunk NBT to and from region files, in the 1.15.2 format
+
+use std::collections::HashMap;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{McError, McResult};
+use crate::nbt::{self, Tag};
+use crate::world::chunk::{bits_needed, MIN_PALETTE_BITS, SECTION_COUNT, SECTION_VOLUME};
+use crate::world::registry::{BlockRegistry, Properties};
+use crate::world::{blocks, pack, unpack, BlockState, ChunkColumn, ChunkPos, ChunkSection};
+
+/// Chunk data version written by 1.15.2
+pub const DATA_VERSION: i32 = 2230;
+
+/// Block state in a section's palette
+#[derive(Serialize, Deserialize)]
+struct PaletteEntry {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(
+        rename = "Properties",
+        default,
+        skip_serializing_if = "Properties::is_empty"
+    )]
+    properties: Properties,
+}
+
+/// Reads a chunk's root tag, as stored in a region file. Also returns whether any blocks weren't
+/// recognised, in which case saving the chunk would replace them with stone
+pub fn read_chunk(
+    root: &Tag,
+    pos: ChunkPos,
+    registry: &BlockRegistry,
+) -> McResult<(ChunkColumn, bool)> {
+    let missing = |what: &str| McError::BadChunk(format!("{:?} is missing {}", pos, what));
+
+    if let Some(version) = root.get("DataVersion").and_then(Tag::as_int) {
+        if version != DATA_VERSION {
+            debug!("chunk {:?} has data version {}", pos, version);
+        }
+    }
+
+    let level = root.get("Level").ok_or_else(|| missing("Level"))?;
+    let stored_pos = (
+        level.get("xPos").and_then(Tag::as_int),
+        level.get("zPos").and_then(Tag::as_int),
+    );
+    if stored_pos != (Some(pos.x), Some(pos.z)) {
+        return Err(McError::BadChunk(format!(
+            "expected {:?} but found {:?}",
+            pos, stored_pos
+        )));
+    }
+
+    let mut column = ChunkColumn::new(pos);
+    let mut lossy = false;
+    let sections = level
+        .get("Sections")
+        .and_then(Tag::as_list)
+        .unwrap_or_default();
+
+    for section in sections {
+        // sections -1 and 16 only hold light
+        let y = section
+            .get("Y")
+            .and_then(Tag::as_int)
+            .ok_or_else(|| missing("Y"))?;
+        if y < 0 || y as usize >= SECTION_COUNT {
+            continue;
+        }
+
+        if let Some((section, unknown_blocks)) = read_section(section, registry)? {
+            column.set_section(y as usize, Some(section));
+            lossy |= unknown_blocks;
+        }
+    }
+
+    if let Some(biomes) = level.get("Biomes").and_then(Tag::as_int_array) {
+        if biomes.len() == column.biomes().len() {
+            column.biomes_mut().copy_from_slice(biomes);
+        }
+    }
+
+    Ok((column, lossy))
+}
+
+/// None for sections without blocks, otherwise the section and whether it had unknown blocks
+fn read_section(section: &Tag, registry: &BlockRegistry) -> McResult<Option<(ChunkSection, bool)>> {
+    let (palette, states) = match (
+        section.get("Palette").and_then(Tag::as_list),
+        section.get("BlockStates").and_then(Tag::as_long_array),
+    ) {
+        (Some(palette), Some(states)) if !palette.is_empty() => (palette, states),
+        _ => return Ok(None),
+    };
+
+    let palette = palette
+        .iter()
+        .map(|entry| palette_state(entry, registry))
+        .collect::<McResult<Vec<_>>>()?;
+    let lossy = palette.iter().any(Option::is_none);
+    let palette: Vec<_> = palette
+        .into_iter()
+        .map(|state| state.unwrap_or(blocks::STONE))
+        .collect();
+
+    let bits = bits_needed(palette.len()).max(MIN_PALETTE_BITS);
+    let mut section = ChunkSection::new();
+    for (i, idx) in unpack(states, bits, SECTION_VOLUME).into_iter().enumerate() {
+        let state = *palette
+            .get(idx as usize)
+            .ok_or_else(|| McError::BadChunk(format!("palette index {} out of range", idx)))?;
+        section.set_block(i & 0xf, i >> 8, (i >> 4) & 0xf, state);
+    }
+
+    Ok(Some((section, lossy)))
+}
+
+/// Global ID of a palette entry, None if unrecognised
+fn palette_state(entry: &Tag, registry: &BlockRegistry) -> McResult<Option<BlockState>> {
+    let PaletteEntry { name, properties } = nbt::from_tag(entry)?;
+    let state = registry.state(&name, &properties);
+    if state.is_none() {
+        trace!("unknown block state {} {:?}", name, properties);
+    }
+    Ok(state)
+}
+
+/// Builds a chunk's root tag for a region file. Entries of the tag it was loaded from are kept,
+/// other than the blocks, biomes and anything derived from them
+pub fn write_chunk(
+    column: &ChunkColumn,
+    original: Option<&Tag>,
+    registry: &BlockRegistry,
+    last_update: i64,
+) -> McResult<Tag> {
+    let pos = column.pos();
+    let mut root = original
+        .cloned()
+        .unwrap_or_else(|| Tag::Compound(Vec::new()));
+    root.insert("DataVersion", Tag::Int(DATA_VERSION));
+    if root.get("Level").and_then(Tag::as_compound).is_none() {
+        root.insert("Level", Tag::Compound(Vec::new()));
+    }
+    let level = root.get_mut("Level").expect("just inserted");
+
+    let mut sections = level
+        .get("Sections")
+        .and_then(Tag::as_list)
+        .map(<[Tag]>::to_vec)
+        .unwrap_or_default();
+    for y in 0..SECTION_COUNT {
+        let existing = sections
+            .iter()
+            .position(|s| s.get("Y").and_then(Tag::as_int) == Some(y as i32));
+        let idx = existing.unwrap_or_else(|| {
+            sections.push(Tag::Compound(vec![("Y".to_owned(), Tag::Byte(y as i8))]));
+            sections.len() - 1
+        });
+
+        let section = &mut sections[idx];
+        match column.section(y).filter(|s| !s.is_empty()) {
+            Some(blocks) => {
+                let (palette, states) = write_section(blocks, registry)?;
+                section.insert("Palette", palette);
+                section.insert("BlockStates", states);
+            }
+            None => {
+                section.remove("Palette");
+                section.remove("BlockStates");
+            }
+        }
+    }
+
+    // drop sections left with only a Y
+    sections.retain(|s| matches!(s, Tag::Compound(entries) if entries.len() > 1));
+    sections.sort_by_key(|s| s.get("Y").and_then(Tag::as_int));
+
+    level.insert("xPos", Tag::Int(pos.x));
+    level.insert("zPos", Tag::Int(pos.z));
+    level.insert("LastUpdate", Tag::Long(last_update));
+    level.insert("Status", Tag::String("full".to_owned()));
+    level.insert("Sections", Tag::List(sections));
+    level.insert("Biomes", Tag::IntArray(column.biomes().to_vec()));
+
+    // recalculated by vanilla when missing
+    level.remove("Heightmaps");
+    level.insert("isLightOn", Tag::Byte(0));
+
+    Ok(root)
+}
+
+/// Palette and packed block states
+fn write_section(section: &ChunkSection, registry: &BlockRegistry) -> McResult<(Tag, Tag)> {
+    let mut palette = Vec::new();
+    let mut palette_idx = HashMap::new();
+    let mut indices = Vec::with_capacity(SECTION_VOLUME);
+    for i in 0..SECTION_VOLUME {
+        let state = section.block(i & 0xf, i >> 8, (i >> 4) & 0xf);
+        let idx = *palette_idx.entry(state).or_insert_with(|| {
+            palette.push(state);
+            palette.len() - 1
+        });
+        indices.push(idx as u64);
+    }
+
+    let palette = palette
+        .into_iter()
+        .map(|state| {
+            let (name, properties) = registry
+                .name(state)
+                .ok_or_else(|| McError::BadChunk(format!("no name for block state {}", state)))?;
+
+            nbt::to_tag(&PaletteEntry {
+                name: name.to_owned(),
+                properties: properties.clone(),
+            })
+        })
+        .collect::<McResult<Vec<_>>>()?;
+
+    let bits = bits_needed(palette.len()).max(MIN_PALETTE_BITS);
+    let states = pack(indices.into_iter(), bits);
+    Ok((Tag::List(palette), Tag::LongArray(states)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Tag {
+        Tag::String(s.to_owned())
+    }
+
+    #[test]
+    fn read_sections() {
+        let registry = BlockRegistry::load("/nonexistent".as_ref()).unwrap();
+        let palette = vec![
+            Tag::Compound(vec![("Name".to_owned(), string("minecraft:air"))]),
+            Tag::Compound(vec![
+                ("Name".to_owned(), string("minecraft:grass_block")),
+                (
+                    "Properties".to_owned(),
+                    Tag::Compound(vec![("snowy".to_owned(), string("false"))]),
+                ),
+            ]),
+            Tag::Compound(vec![("Name".to_owned(), string("minecraft:mystery"))]),
+        ];
+
+        // bottom layer of grass, one unknown block above
+        let indices = (0..SECTION_VOLUME).map(|i| match i {
+            0..=255 => 1,
+            256 => 2,
+            _ => 0,
+        });
+        let section = Tag::Compound(vec![
+            ("Y".to_owned(), Tag::Byte(4)),
+            ("Palette".to_owned(), Tag::List(palette)),
+            ("BlockStates".to_owned(), Tag::LongArray(pack(indices, 4))),
+        ]);
+        let light_only = Tag::Compound(vec![("Y".to_owned(), Tag::Byte(-1))]);
+
+        let root = Tag::Compound(vec![
+            ("DataVersion".to_owned(), Tag::Int(DATA_VERSION)),
+            (
+                "Level".to_owned(),
+                Tag::Compound(vec![
+                    ("xPos".to_owned(), Tag::Int(-3)),
+                    ("zPos".to_owned(), Tag::Int(7)),
+                    ("Sections".to_owned(), Tag::List(vec![light_only, section])),
+                    ("Biomes".to_owned(), Tag::IntArray(vec![4; 1024])),
+                ]),
+            ),
+        ]);
+
+        let (column, lossy) = read_chunk(&root, ChunkPos::new(-3, 7), &registry).unwrap();
+        assert!(lossy);
+        assert_eq!(column.block(5, 64, 9), blocks::GRASS_BLOCK);
+        assert_eq!(column.block(0, 65, 0), blocks::STONE);
+        assert_eq!(column.block(1, 65, 0), crate::world::AIR);
+        assert!(column.section(0).is_none());
+        assert_eq!(column.biomes()[0], 4);
+
+        assert!(read_chunk(&root, ChunkPos::new(0, 0), &registry).is_err());
+    }
+
+    #[test]
+    fn write_keeps_unknown_entries() {
+        let registry = BlockRegistry::load("/nonexistent".as_ref()).unwrap();
+        let pos = ChunkPos::new(2, -5);
+        let light = Tag::Compound(vec![
+            ("Y".to_owned(), Tag::Byte(-1)),
+            ("SkyLight".to_owned(), Tag::ByteArray(vec![-1; 2048])),
+        ]);
+        let original = Tag::Compound(vec![(
+            "Level".to_owned(),
+            Tag::Compound(vec![
+                ("xPos".to_owned(), Tag::Int(pos.x)),
+                ("zPos".to_owned(), Tag::Int(pos.z)),
+                ("Entities".to_owned(), Tag::List(vec![Tag::Int(1)])),
+                ("Sections".to_owned(), Tag::List(vec![light.clone()])),
+            ]),
+        )]);
+
+        let mut column = ChunkColumn::new(pos);
+        column.set_block(3, 200, 4, blocks::DIRT);
+        column.biomes_mut()[7] = 3;
+
+        let root = write_chunk(&column, Some(&original), &registry, 1234).unwrap();
+        let level = root.get("Level").unwrap();
+        assert_eq!(level.get("Entities"), Some(&Tag::List(vec![Tag::Int(1)])));
+        let sections = level.get("Sections").and_then(Tag::as_list).unwrap();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0], light);
+
+        let (read, lossy) = read_chunk(&root, pos, &registry).unwrap();
+        assert!(!lossy);
+        assert_eq!(read.block(3, 200, 4), blocks::DIRT);
+        assert_eq!(read.block(3, 201, 4), crate::world::AIR);
+        assert_eq!(read.biomes()[7], 3);
+    }
+
+    #[test]
+    fn unknown_properties_are_lossy() {
+        let registry = BlockRegistry::load("/nonexistent".as_ref()).unwrap();
+        let pos = ChunkPos::new(0, 0);
+        let palette = vec![
+            Tag::Compound(vec![("Name".to_owned(), string("minecraft:air"))]),
+            Tag::Compound(vec![
+                ("Name".to_owned(), string("minecraft:oak_log")),
+                (
+                    "Properties".to_owned(),
+                    Tag::Compound(vec![("axis".to_owned(), string("x"))]),
+                ),
+            ]),
+        ];
+        let indices = (0..SECTION_VOLUME).map(|i| if i == 0 { 1 } else { 0 });
+        let root = Tag::Compound(vec![(
+            "Level".to_owned(),
+            Tag::Compound(vec![
+                ("xPos".to_owned(), Tag::Int(pos.x)),
+                ("zPos".to_owned(), Tag::Int(pos.z)),
+                (
+                    "Sections".to_owned(),
+                    Tag::List(vec![Tag::Compound(vec![
+                        ("Y".to_owned(), Tag::Byte(0)),
+                        ("Palette".to_owned(), Tag::List(palette)),
+                        ("BlockStates".to_owned(), Tag::LongArray(pack(indices, 4))),
+                    ])]),
+                ),
+            ]),
+        )]);
+
+        let (_, lossy) = read_chunk(&root, pos, &registry).unwrap();
+        assert!(lossy);
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use crate::world::{ChunkColumn, ChunkPos};
+
+/// Recently loaded or generated columns, so chunks coming back into view aren't loaded again
+pub struct ChunkCache {
+    capacity: usize,
+    /// Each with the time it was last used
+    columns: HashMap<ChunkPos, (u64, ChunkColumn)>,
+    clock: u64,
+}
+
+impl ChunkCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            columns: HashMap::with_capacity(capacity),
+            clock: 0,
+        }
+    }
+
+    pub fn get(&mut self, pos: ChunkPos) -> Option<ChunkColumn> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.columns.get_mut(&pos).map(|(used, column)| {
+            *used = clock;
+            column.clone()
+        })
+    }
+
+    pub fn insert(&mut self, column: ChunkColumn) {
+        if self.columns.len() >= self.capacity && !self.columns.contains_key(&column.pos()) {
+            self.evict();
+        }
+
+        self.clock += 1;
+        self.columns.insert(column.pos(), (self.clock, column));
+    }
+
+    pub fn remove(&mut self, pos: ChunkPos) {
+        self.columns.remove(&pos);
+    }
+
+    /// Drops the least recently used quarter at once, rather than searching for the oldest on
+    /// every insert
+    fn evict(&mut self) {
+        let mut uses: Vec<u64> = self.columns.values().map(|(used, _)| *used).collect();
+        if uses.is_empty() {
+            return;
+        }
+
+        uses.sort_unstable();
+        let count = (uses.len() / 4).max(1);
+        let cutoff = uses[count - 1];
+        self.columns.retain(|_, (used, _)| *used > cutoff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = ChunkCache::new(4);
+        for x in 0..4 {
+            cache.insert(ChunkColumn::new(ChunkPos::new(x, 0)));
+        }
+        assert!(cache.get(ChunkPos::new(0, 0)).is_some());
+
+        cache.insert(ChunkColumn::new(ChunkPos::new(4, 0)));
+        assert!(cache.get(ChunkPos::new(1, 0)).is_none());
+        for x in [0, 2, 3, 4].iter() {
+            assert!(cache.get(ChunkPos::new(*x, 0)).is_some());
+        }
+
+        cache.remove(ChunkPos::new(3, 0));
+        assert!(cache.get(ChunkPos::new(3, 0)).is_none());
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+
+use crate::connection::ClientBoundPacket;
+use crate::nbt::Tag;
+use crate::packet::play::client as play;
+use crate::packet::{write_var_int, PacketBuilder};
+
+/// Index into the global block state palette
+pub type BlockState = u16;
+
+pub const AIR: BlockState = 0;
+
+/// Sections per column, each 16 blocks high
+pub const SECTION_COUNT: usize = 16;
+
+pub const CHUNK_HEIGHT: usize = SECTION_COUNT * 16;
+
+pub(crate) const SECTION_VOLUME: usize = 16 * 16 * 16;
+
+/// Biomes are stored per 4x4x4 cube
+const BIOME_COUNT: usize = 4 * 4 * 64;
+
+const PLAINS_BIOME: i32 = 1;
+
+/// Sections with more distinct states than fit in this many bits use the global palette
+const MAX_PALETTE_BITS: u8 = 8;
+
+pub(crate) const MIN_PALETTE_BITS: u8 = 4;
+
+/// Bits per entry when using the global palette
+const GLOBAL_PALETTE_BITS: u8 = 14;
+
+/// Bits per heightmap entry, enough for 0-256 inclusive
+const HEIGHTMAP_BITS: u8 = 9;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+#[derive(Clone)]
+pub struct ChunkSection {
+    /// Indexed by y, z then x
+    blocks: Box<[BlockState; SECTION_VOLUME]>,
+    non_air_count: u16,
+}
+
+#[derive(Clone)]
+pub struct ChunkColumn {
+    pos: ChunkPos,
+    sections: [Option<ChunkSection>; SECTION_COUNT],
+    biomes: Box<[i32; BIOME_COUNT]>,
+}
+
+impl ChunkPos {
+    pub fn new(x: i32, z: i32) -> Self {
+        Self { x, z }
+    }
+
+    /// Chunk containing the given world coordinates
+    pub fn containing(x: f64, z: f64) -> Self {
+        Self::new((x.floor() as i32) >> 4, (z.floor() as i32) >> 4)
+    }
+
+    /// All chunks in the square of the given radius around this one
+    pub fn surrounding(self, radius: i32) -> impl Iterator<Item = ChunkPos> {
+        (-radius..=radius).flat_map(move |dx| {
+            (-radius..=radius).map(move |dz| ChunkPos::new(self.x + dx, self.z + dz))
+        })
+    }
+
+    /// Chebyshev distance in chunks
+    pub fn distance(self, other: ChunkPos) -> i32 {
+        (self.x - other.x).abs().max((self.z - other.z).abs())
+    }
+}
+
+impl ChunkSection {
+    pub fn new() -> Self {
+        Self {
+            blocks: Box::new([AIR; SECTION_VOLUME]),
+            non_air_count: 0,
+        }
+    }
+
+    pub fn block(&self, x: usize, y: usize, z: usize) -> BlockState {
+        self.blocks[section_index(x, y, z)]
+    }
+
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, state: BlockState) {
+        let block = &mut self.blocks[section_index(x, y, z)];
+        match (*block == AIR, state == AIR) {
+            (true, false) => self.non_air_count += 1,
+            (false, true) => self.non_air_count -= 1,
+            _ => {}
+        }
+
+        *block = state;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.non_air_count == 0
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        // build palette in order of first appearance
+        let mut palette = Vec::new();
+        let mut palette_lookup = HashMap::new();
+        for state in self.blocks.iter() {
+            palette_lookup.entry(*state).or_insert_with(|| {
+                palette.push(*state);
+                palette.len() - 1
+            });
+        }
+
+        let bits = bits_needed(palette.len()).max(MIN_PALETTE_BITS);
+
+        buf.extend_from_slice(&(self.non_air_count as i16).to_be_bytes());
+        let data = if bits <= MAX_PALETTE_BITS {
+            buf.push(bits);
+            write_var_int(buf, palette.len() as i32);
+            for state in &palette {
+                write_var_int(buf, *state as i32);
+            }
+
+            pack(self.blocks.iter().map(|b| palette_lookup[b] as u64), bits)
+        } else {
+            buf.push(GLOBAL_PALETTE_BITS);
+            pack(self.blocks.iter().map(|b| *b as u64), GLOBAL_PALETTE_BITS)
+        };
+
+        write_var_int(buf, data.len() as i32);
+        for long in data {
+            buf.extend_from_slice(&long.to_be_bytes());
+        }
+    }
+}
+
+impl Default for ChunkSection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChunkColumn {
+    /// All air, all plains
+    pub fn new(pos: ChunkPos) -> Self {
+        Self {
+            pos,
+            sections: Default::default(),
+            biomes: Box::new([PLAINS_BIOME; BIOME_COUNT]),
+        }
+    }
+
+    pub fn pos(&self) -> ChunkPos {
+        self.pos
+    }
+
+    /// Coordinates are relative to the chunk
+    pub fn block(&self, x: usize, y: usize, z: usize) -> BlockState {
+        self.sections[y / 16]
+            .as_ref()
+            .map(|section| section.block(x, y % 16, z))
+            .unwrap_or(AIR)
+    }
+
+    /// Coordinates are relative to the chunk
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, state: BlockState) {
+        let section = &mut self.sections[y / 16];
+        if section.is_none() && state == AIR {
+            return;
+        }
+
+        section
+            .get_or_insert_with(ChunkSection::new)
+            .set_block(x, y % 16, z, state);
+    }
+
+    pub fn section(&self, idx: usize) -> Option<&ChunkSection> {
+        self.sections[idx].as_ref()
+    }
+
+    pub fn set_section(&mut self, idx: usize, section: Option<ChunkSection>) {
+        self.sections[idx] = section;
+    }
+
+    pub fn biomes(&self) -> &[i32] {
+        &self.biomes[..]
+    }
+
+    pub fn biomes_mut(&mut self) -> &mut [i32] {
+        &mut self.biomes[..]
+    }
+
+    /// Y of the block above the highest non-air block in each column, indexed by z then x
+    pub fn heightmap(&self) -> [u16; 256] {
+        let mut heights = [0u16; 256];
+        for (i, height) in heights.iter_mut().enumerate() {
+            let (x, z) = (i % 16, i / 16);
+            *height = (0..CHUNK_HEIGHT)
+                .rev()
+                .find(|y| self.block(x, *y, z) != AIR)
+                .map(|y| y as u16 + 1)
+                .unwrap_or(0);
+        }
+
+        heights
+    }
+
+    /// Full MapChunk packet
+    pub fn to_packet(&self) -> ClientBoundPacket {
+        let mut bitmask = 0;
+        let mut data = Vec::with_capacity(8192);
+        for (i, section) in self.sections.iter().enumerate() {
+            if let Some(section) = section.as_ref().filter(|s| !s.is_empty()) {
+                bitmask |= 1 << i;
+                section.encode(&mut data);
+            }
+        }
+
+        let heightmap = pack(self.heightmap().iter().map(|h| *h as u64), HEIGHTMAP_BITS);
+        let heightmaps = Tag::Compound(vec![
+            (
+                "MOTION_BLOCKING".to_owned(),
+                Tag::LongArray(heightmap.clone()),
+            ),
+            ("WORLD_SURFACE".to_owned(), Tag::LongArray(heightmap)),
+        ]);
+
+        let mut packet = PacketBuilder::new(play::MapChunk::ID);
+        packet
+            .int(self.pos.x)
+            .int(self.pos.z)
+            .bool(true) // full chunk
+            .var_int(bitmask)
+            .nbt(&heightmaps);
+
+        for biome in self.biomes.iter() {
+            packet.int(*biome);
+        }
+
+        // TODO block entities
+        packet.byte_array(&data).var_int(0).build()
+    }
+}
+
+fn section_index(x: usize, y: usize, z: usize) -> usize {
+    debug_assert!(x < 16 && y < 16 && z < 16);
+    (y << 8) | (z << 4) | x
+}
+
+pub(crate) fn bits_needed(distinct: usize) -> u8 {
+    let mut bits = 0;
+    while (1 << bits) < distinct {
+        bits += 1;
+    }
+    bits
+}
+
+/// Packs values into longs, entries spanning across long boundaries as of 1.15
+pub fn pack(values: impl Iterator<Item = u64>, bits: u8) -> Vec<i64> {
+    let bits = bits as usize;
+    let mut longs = Vec::new();
+    for (i, value) in values.enumerate() {
+        let bit_offset = i * bits;
+        let (idx, shift) = (bit_offset / 64, bit_offset % 64);
+        let end_idx = (bit_offset + bits - 1) / 64;
+        if longs.len() <= end_idx {
+            longs.resize(end_idx + 1, 0u64);
+        }
+
+        longs[idx] |= value << shift;
+        if end_idx != idx {
+            longs[end_idx] |= value >> (64 - shift);
+        }
+    }
+
+    longs.into_iter().map(|l| l as i64).collect()
+}
+
+/// Inverse of [pack]
+pub fn unpack(longs: &[i64], bits: u8, count: usize) -> Vec<u64> {
+    let bits = bits as usize;
+    let mask = (1u64 << bits) - 1;
+    (0..count)
+        .map(|i| {
+            let bit_offset = i * bits;
+            let (idx, shift) = (bit_offset / 64, bit_offset % 64);
+            let end_idx = (bit_offset + bits - 1) / 64;
+
+            let mut value = longs.get(idx).map(|l| *l as u64 >> shift).unwrap_or(0);
+            if end_idx != idx {
+                value |= longs
+                    .get(end_idx)
+                    .map(|l| (*l as u64) << (64 - shift))
+                    .unwrap_or(0);
+            }
+            value & mask
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_round_trip() {
+        for &bits in &[4u8, 5, 9, 14] {
+            let values: Vec<u64> = (0..4096).map(|i| i % (1 << bits)).collect();
+            let packed = pack(values.iter().copied(), bits);
+            assert_eq!(packed.len(), (4096 * bits as usize + 63) / 64);
+            assert_eq!(unpack(&packed, bits, values.len()), values);
+        }
+    }
+
+    #[test]
+    fn heightmap_spans_longs() {
+        // 256 entries * 9 bits fit exactly into 36 longs
+        let packed = pack((0..256).map(|_| 256), HEIGHTMAP_BITS);
+        assert_eq!(packed.len(), 36);
+        assert_eq!(
+            packed[0] as u64,
+            (0..7).map(|i| 1u64 << (i * 9 + 8)).sum::<u64>()
+        );
+        assert_eq!(packed[1] as u64 & 0xff, 1 << 7); // 8th entry spilled over
+    }
+
+    #[test]
+    fn section_blocks() {
+        let mut chunk = ChunkColumn::new(ChunkPos::new(0, 0));
+        assert_eq!(chunk.heightmap()[0], 0);
+
+        chunk.set_block(1, 70, 2, 1);
+        assert_eq!(chunk.block(1, 70, 2), 1);
+        assert_eq!(chunk.block(1, 71, 2), AIR);
+        assert_eq!(chunk.heightmap()[2 * 16 + 1], 71);
+        assert!(chunk.section(4).unwrap().non_air_count == 1);
+
+        chunk.set_block(1, 70, 2, AIR);
+        assert!(chunk.section(4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn chunk_pos() {
+        assert_eq!(ChunkPos::containing(15.9, -0.1), ChunkPos::new(0, -1));
+        assert_eq!(ChunkPos::containing(-16.0, 16.0), ChunkPos::new(-1, 1));
+        assert_eq!(ChunkPos::new(0, 0).surrounding(2).count(), 25);
+    }
+}
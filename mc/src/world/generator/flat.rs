@@ -0,0 +1,200 @@
+//! Superflat worlds of uniform layers
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{McError, McResult};
+use crate::nbt::{self, Tag};
+use crate::world::generator::{biome_id, biome_name, biomes, namespaced, ChunkGenerator};
+use crate::world::registry::Properties;
+use crate::world::{BlockRegistry, BlockState, ChunkColumn, ChunkPos, AIR, CHUNK_HEIGHT};
+
+/// Vanilla's classic flat preset
+pub const DEFAULT_PRESET: &str =
+    "minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains";
+
+pub struct FlatGenerator {
+    /// Block names and thicknesses, bottom up
+    layers: Vec<(String, usize)>,
+    /// State at each height
+    column: Vec<BlockState>,
+    biome: i32,
+}
+
+/// `generatorOptions` as stored in `level.dat`
+#[derive(Serialize, Deserialize)]
+struct FlatOptions {
+    layers: Vec<FlatLayer>,
+    biome: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FlatLayer {
+    block: String,
+    height: i32,
+}
+
+impl FlatGenerator {
+    pub fn new(
+        layers: Vec<(String, usize)>,
+        biome: i32,
+        registry: &BlockRegistry,
+    ) -> McResult<Self> {
+        let height = layers
+            .iter()
+            .try_fold(0usize, |height, (_, count)| height.checked_add(*count))
+            .filter(|height| *height <= CHUNK_HEIGHT)
+            .ok_or_else(|| {
+                McError::BadGeneratorSettings(format!(
+                    "layers are more than the world height of {} blocks",
+                    CHUNK_HEIGHT
+                ))
+            })?;
+
+        let mut column = Vec::with_capacity(height);
+        for (name, count) in &layers {
+            let state = registry
+                .state(name, &Properties::new())
+                .ok_or_else(|| McError::BadGeneratorSettings(format!("unknown block {}", name)))?;
+            column.resize(column.len() + count, state);
+        }
+
+        Ok(Self {
+            layers,
+            column,
+            biome,
+        })
+    }
+
+    /// Parses a preset as shown in the client's customisation screen, like
+    /// `minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains`. The biome and
+    /// anything after it is optional, and an empty preset is the classic flat world
+    pub fn from_preset(preset: &str, registry: &BlockRegistry) -> McResult<Self> {
+        let preset = match preset.trim() {
+            "" => DEFAULT_PRESET,
+            preset => preset,
+        };
+        let bad = |reason: String| McError::BadGeneratorSettings(reason);
+
+        let mut parts = preset.split(';');
+        let layers = parts
+            .next()
+            .unwrap_or_default()
+            .split(',')
+            .map(|layer| match layer.find('*') {
+                Some(idx) => {
+                    let count = layer[..idx]
+                        .trim()
+                        .parse()
+                        .map_err(|_| bad(format!("invalid layer '{}'", layer)))?;
+                    Ok((namespaced(&layer[idx + 1..]), count))
+                }
+                None => Ok((namespaced(layer), 1)),
+            })
+            .collect::<McResult<Vec<_>>>()?;
+
+        let biome = match parts.next() {
+            Some(name) => {
+                biome_id(&namespaced(name)).ok_or_else(|| bad(format!("unknown biome {}", name)))?
+            }
+            None => biomes::PLAINS,
+        };
+
+        Self::new(layers, biome, registry)
+    }
+
+    /// From the options vanilla stores in `level.dat`
+    pub fn from_options(options: &Tag, registry: &BlockRegistry) -> McResult<Self> {
+        let options: FlatOptions = nbt::from_tag(options)?;
+        let layers = options
+            .layers
+            .into_iter()
+            .map(|layer| (layer.block, layer.height.max(0) as usize))
+            .collect();
+        let biome = biome_id(&options.biome).unwrap_or(biomes::PLAINS);
+
+        Self::new(layers, biome, registry)
+    }
+}
+
+impl ChunkGenerator for FlatGenerator {
+    fn generate(&self, pos: ChunkPos) -> ChunkColumn {
+        let mut chunk = ChunkColumn::new(pos);
+        for (y, state) in self.column.iter().enumerate() {
+            if *state == AIR {
+                continue;
+            }
+
+            for x in 0..16 {
+                for z in 0..16 {
+                    chunk.set_block(x, y, z, *state);
+                }
+            }
+        }
+
+        for biome in chunk.biomes_mut() {
+            *biome = self.biome;
+        }
+        chunk
+    }
+
+    fn spawn_point(&self) -> (i32, i32, i32) {
+        (0, self.column.len() as i32, 0)
+    }
+
+    fn name(&self) -> &'static str {
+        "flat"
+    }
+
+    fn options(&self) -> Option<Tag> {
+        let options = FlatOptions {
+            layers: self
+                .layers
+                .iter()
+                .map(|(block, height)| FlatLayer {
+                    block: block.clone(),
+                    height: *height as i32,
+                })
+                .collect(),
+            biome: biome_name(self.biome)
+                .unwrap_or("minecraft:plains")
+                .to_owned(),
+        };
+        nbt::to_tag(&options).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::blocks;
+
+    #[test]
+    fn presets() {
+        let registry = BlockRegistry::load("/nonexistent".as_ref()).unwrap();
+        let flat = FlatGenerator::from_preset(
+            "minecraft:bedrock, 3*stone,minecraft:grass_block;minecraft:desert;village",
+            &registry,
+        )
+        .unwrap();
+
+        let chunk = flat.generate(ChunkPos::new(4, -2));
+        assert_eq!(chunk.block(3, 0, 3), blocks::BEDROCK);
+        assert_eq!(chunk.block(3, 3, 3), blocks::STONE);
+        assert_eq!(chunk.block(3, 4, 3), blocks::GRASS_BLOCK);
+        assert_eq!(chunk.block(3, 5, 3), AIR);
+        assert_eq!(chunk.biomes()[0], biomes::DESERT);
+        assert_eq!(flat.spawn_point(), (0, 5, 0));
+
+        // survives a round trip through level.dat
+        let saved = FlatGenerator::from_options(&flat.options().unwrap(), &registry).unwrap();
+        assert_eq!(saved.column, flat.column);
+        assert_eq!(saved.biome, biomes::DESERT);
+
+        assert!(FlatGenerator::from_preset("", &registry).is_ok());
+        assert!(FlatGenerator::from_preset("2*minecraft:unobtainium", &registry).is_err());
+        assert!(FlatGenerator::from_preset("x*stone", &registry).is_err());
+        assert!(FlatGenerator::from_preset("300*stone", &registry).is_err());
+        assert!(FlatGenerator::from_preset("4000000000*stone", &registry).is_err());
+        assert!(FlatGenerator::from_preset("18446744073709551615*stone,stone", &registry).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+//! Terrain for chunks that haven't been saved
+
+use log::*;
+
+pub use flat::FlatGenerator;
+pub use overworld::OverworldGenerator;
+
+use crate::error::McResult;
+use crate::nbt::Tag;
+use crate::world::{hash_seed, BlockRegistry, ChunkColumn, ChunkPos};
+
+mod flat;
+mod noise;
+mod overworld;
+
+/// Biome IDs for 1.15.2
+pub mod biomes {
+    pub const OCEAN: i32 = 0;
+    pub const PLAINS: i32 = 1;
+    pub const DESERT: i32 = 2;
+    pub const MOUNTAINS: i32 = 3;
+    pub const FOREST: i32 = 4;
+    pub const TAIGA: i32 = 5;
+    pub const SWAMP: i32 = 6;
+    pub const RIVER: i32 = 7;
+    pub const FROZEN_OCEAN: i32 = 10;
+    pub const SNOWY_TUNDRA: i32 = 12;
+    pub const BEACH: i32 = 16;
+    pub const DEEP_OCEAN: i32 = 24;
+}
+
+const BIOME_NAMES: &[(&str, i32)] = &[
+    ("minecraft:ocean", biomes::OCEAN),
+    ("minecraft:plains", biomes::PLAINS),
+    ("minecraft:desert", biomes::DESERT),
+    ("minecraft:mountains", biomes::MOUNTAINS),
+    ("minecraft:forest", biomes::FOREST),
+    ("minecraft:taiga", biomes::TAIGA),
+    ("minecraft:swamp", biomes::SWAMP),
+    ("minecraft:river", biomes::RIVER),
+    ("minecraft:frozen_ocean", biomes::FROZEN_OCEAN),
+    ("minecraft:snowy_tundra", biomes::SNOWY_TUNDRA),
+    ("minecraft:beach", biomes::BEACH),
+    ("minecraft:deep_ocean", biomes::DEEP_OCEAN),
+];
+
+/// Generates chunks on worker threads, so must be shareable
+pub trait ChunkGenerator: Send + Sync {
+    /// The same for a given position, so chunks can be regenerated rather than kept around
+    fn generate(&self, pos: ChunkPos) -> ChunkColumn;
+
+    /// Block position players spawn at in a new world
+    fn spawn_point(&self) -> (i32, i32, i32);
+
+    /// `generatorName` in `level.dat`
+    fn name(&self) -> &'static str;
+
+    /// `generatorOptions` in `level.dat`
+    fn options(&self) -> Option<Tag> {
+        None
+    }
+}
+
+/// How new worlds are generated, from `server.properties`
+#[derive(Debug, Clone, Default)]
+pub struct GeneratorSettings {
+    /// `default` or `flat`
+    pub level_type: String,
+    /// Layer preset for flat worlds
+    pub preset: String,
+    /// Empty for a random seed
+    pub seed: String,
+}
+
+impl GeneratorSettings {
+    /// Numbers are used as they are and anything else is hashed, as in vanilla
+    pub fn seed(&self) -> i64 {
+        let seed = self.seed.trim();
+        if seed.is_empty() {
+            let mut bytes = [0u8; 8];
+            if let Err(e) = openssl::rand::rand_bytes(&mut bytes) {
+                warn!("failed to generate a random seed: {}", e);
+            }
+            return i64::from_le_bytes(bytes);
+        }
+
+        seed.parse()
+            .unwrap_or_else(|_| i64::from(java_string_hash(seed)))
+    }
+
+    /// Generator for a new world
+    pub fn generator(
+        &self,
+        seed: i64,
+        registry: &BlockRegistry,
+    ) -> McResult<Box<dyn ChunkGenerator>> {
+        match self.level_type.to_lowercase().as_str() {
+            "flat" => Ok(Box::new(FlatGenerator::from_preset(
+                &self.preset,
+                registry,
+            )?)),
+            "default" => Ok(Box::new(OverworldGenerator::new(
+                hash_seed(seed),
+                registry,
+            )?)),
+            other => {
+                warn!("unsupported level type '{}', using default", other);
+                Ok(Box::new(OverworldGenerator::new(
+                    hash_seed(seed),
+                    registry,
+                )?))
+            }
+        }
+    }
+}
+
+/// Generator of an existing world, from its `level.dat`
+pub fn saved_generator(
+    name: &str,
+    options: Option<&Tag>,
+    seed: i64,
+    registry: &BlockRegistry,
+) -> McResult<Box<dyn ChunkGenerator>> {
+    match (name, options) {
+        ("flat", Some(options)) => Ok(Box::new(FlatGenerator::from_options(options, registry)?)),
+        ("flat", None) => Ok(Box::new(FlatGenerator::from_preset("", registry)?)),
+        _ => Ok(Box::new(OverworldGenerator::new(
+            hash_seed(seed),
+            registry,
+        )?)),
+    }
+}
+
+pub fn biome_id(name: &str) -> Option<i32> {
+    BIOME_NAMES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, id)| *id)
+}
+
+pub fn biome_name(id: i32) -> Option<&'static str> {
+    BIOME_NAMES
+        .iter()
+        .find(|(_, i)| *i == id)
+        .map(|(name, _)| *name)
+}
+
+/// Adds the default namespace if there isn't one
+fn namespaced(name: &str) -> String {
+    let name = name.trim();
+    if name.contains(':') {
+        name.to_owned()
+    } else {
+        format!("minecraft:{}", name)
+    }
+}
+
+/// Java's `String::hashCode`
+fn java_string_hash(s: &str) -> i32 {
+    s.encode_utf16()
+        .fold(0i32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as i32))
+}
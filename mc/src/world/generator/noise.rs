@@ -0,0 +1,152 @@
+//! Seeded randomness and gradient noise for terrain
+
+/// SplitMix64, small and fast with well distributed output
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// In `0..n`
+    pub fn next_below(&mut self, n: u32) -> u32 {
+        (self.next_u64() % n as u64) as u32
+    }
+
+    /// In `0..1`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Ken Perlin's improved noise
+struct Perlin {
+    /// Shuffled 0-255, repeated to avoid wrapping indices
+    permutation: [u8; 512],
+    /// Moves the origin so layers sharing coordinates differ
+    offset: (f64, f64, f64),
+}
+
+/// Several layers of noise at doubling frequencies and halving amplitudes
+pub struct Octaves {
+    layers: Vec<Perlin>,
+}
+
+impl Perlin {
+    fn new(random: &mut Random) -> Self {
+        let mut values: Vec<u8> = (0..=255).collect();
+        for i in (1..values.len()).rev() {
+            let j = random.next_below(i as u32 + 1) as usize;
+            values.swap(i, j);
+        }
+
+        let mut permutation = [0u8; 512];
+        for (i, p) in permutation.iter_mut().enumerate() {
+            *p = values[i & 255];
+        }
+
+        let offset = (
+            random.next_f64() * 256.0,
+            random.next_f64() * 256.0,
+            random.next_f64() * 256.0,
+        );
+        Self {
+            permutation,
+            offset,
+        }
+    }
+
+    /// Roughly in `-1..1`
+    fn sample(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (x, y, z) = (x + self.offset.0, y + self.offset.1, z + self.offset.2);
+        let (xf, yf, zf) = (x.floor(), y.floor(), z.floor());
+        let (xi, yi, zi) = (
+            (xf as i64 & 255) as usize,
+            (yf as i64 & 255) as usize,
+            (zf as i64 & 255) as usize,
+        );
+        let (x, y, z) = (x - xf, y - yf, z - zf);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let p = |i: usize| self.permutation[i] as usize;
+        let (a, b) = (p(xi) + yi, p(xi + 1) + yi);
+        let (aa, ab, ba, bb) = (p(a) + zi, p(a + 1) + zi, p(b) + zi, p(b + 1) + zi);
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p(aa), x, y, z), grad(p(ba), x - 1.0, y, z)),
+                lerp(
+                    u,
+                    grad(p(ab), x, y - 1.0, z),
+                    grad(p(bb), x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p(aa + 1), x, y, z - 1.0),
+                    grad(p(ba + 1), x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(p(ab + 1), x, y - 1.0, z - 1.0),
+                    grad(p(bb + 1), x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+}
+
+impl Octaves {
+    pub fn new(random: &mut Random, count: usize) -> Self {
+        Self {
+            layers: (0..count).map(|_| Perlin::new(random)).collect(),
+        }
+    }
+
+    /// Roughly in `-1..1`, though mostly within half that
+    pub fn sample(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (mut total, mut max) = (0.0, 0.0);
+        let (mut frequency, mut amplitude) = (1.0, 1.0);
+        for layer in &self.layers {
+            total += layer.sample(x * frequency, y * frequency, z * frequency) * amplitude;
+            max += amplitude;
+            frequency *= 2.0;
+            amplitude *= 0.5;
+        }
+
+        total / max
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// Dot product with one of 12 gradient directions
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = match h {
+        0..=3 => y,
+        12 | 14 => x,
+        _ => z,
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
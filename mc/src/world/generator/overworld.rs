@@ -0,0 +1,398 @@
+//! Noise based terrain resembling the vanilla overworld, with hills, oceans, caves and trees
+
+use crate::error::{McError, McResult};
+use crate::world::generator::biomes;
+use crate::world::generator::noise::{Octaves, Random};
+use crate::world::generator::ChunkGenerator;
+use crate::world::registry::Properties;
+use crate::world::{BlockRegistry, BlockState, ChunkColumn, ChunkPos, AIR, CHUNK_HEIGHT};
+
+/// Highest water block in oceans
+pub const SEA_LEVEL: usize = 62;
+
+/// Blocks per unit of noise input
+const CONTINENT_SCALE: f64 = 512.0;
+const DETAIL_SCALE: f64 = 48.0;
+const CLIMATE_SCALE: f64 = 768.0;
+const CAVE_SCALE: f64 = 64.0;
+const CAVE_VERTICAL_SCALE: f64 = 32.0;
+
+/// Continent noise above which the land rises into mountains
+const MOUNTAIN_THRESHOLD: f64 = 0.25;
+
+/// Climate noise thresholds
+const COLD: f64 = -0.25;
+const HOT: f64 = 0.2;
+
+/// How close to zero both cave noises must be, giving tunnels where their zero surfaces meet
+const CAVE_WIDTH: f64 = 0.04;
+
+/// Highest layer that may have bedrock, becoming rarer upwards
+const BEDROCK_LAYERS: usize = 5;
+
+/// Distance to search for dry land to spawn on
+const SPAWN_SEARCH_RADIUS: i32 = 512;
+
+pub struct OverworldGenerator {
+    seed: u64,
+    continents: Octaves,
+    detail: Octaves,
+    temperature: Octaves,
+    humidity: Octaves,
+    caves: (Octaves, Octaves),
+    blocks: Blocks,
+}
+
+struct Blocks {
+    stone: BlockState,
+    dirt: BlockState,
+    grass: BlockState,
+    snowy_grass: BlockState,
+    sand: BlockState,
+    sandstone: BlockState,
+    gravel: BlockState,
+    water: BlockState,
+    bedrock: BlockState,
+    log: BlockState,
+    leaves: BlockState,
+}
+
+/// Terrain at a single x and z
+#[derive(Copy, Clone)]
+struct Column {
+    /// Top solid block
+    height: usize,
+    biome: i32,
+}
+
+impl OverworldGenerator {
+    /// Seeded with the hashed seed the client is sent, as the world seed itself is secret
+    pub fn new(hashed_seed: i64, registry: &BlockRegistry) -> McResult<Self> {
+        let seed = hashed_seed as u64;
+        let mut random = Random::new(seed);
+        Ok(Self {
+            seed,
+            continents: Octaves::new(&mut random, 6),
+            detail: Octaves::new(&mut random, 3),
+            temperature: Octaves::new(&mut random, 3),
+            humidity: Octaves::new(&mut random, 3),
+            caves: (Octaves::new(&mut random, 2), Octaves::new(&mut random, 2)),
+            blocks: Blocks::new(registry)?,
+        })
+    }
+
+    fn column(&self, x: i32, z: i32) -> Column {
+        let (x, z) = (x as f64, z as f64);
+        let continent = self
+            .continents
+            .sample(x / CONTINENT_SCALE, 0.0, z / CONTINENT_SCALE);
+
+        let mut height = SEA_LEVEL as f64 + 2.0 + continent * 48.0;
+        if continent > MOUNTAIN_THRESHOLD {
+            height += (continent - MOUNTAIN_THRESHOLD) * 160.0;
+        }
+        height += self.detail.sample(x / DETAIL_SCALE, 0.0, z / DETAIL_SCALE) * 6.0;
+        let height = height
+            .max(BEDROCK_LAYERS as f64 + 1.0)
+            .min((CHUNK_HEIGHT - 20) as f64) as usize;
+
+        let temperature = self
+            .temperature
+            .sample(x / CLIMATE_SCALE, 0.0, z / CLIMATE_SCALE);
+        let humidity = self
+            .humidity
+            .sample(x / CLIMATE_SCALE, 0.0, z / CLIMATE_SCALE);
+        let dry_heat = temperature > HOT && humidity < 0.0;
+
+        let biome = if height + 4 < SEA_LEVEL {
+            if temperature < COLD {
+                biomes::FROZEN_OCEAN
+            } else if height + 20 < SEA_LEVEL {
+                biomes::DEEP_OCEAN
+            } else {
+                biomes::OCEAN
+            }
+        } else if height <= SEA_LEVEL + 1 {
+            if dry_heat {
+                biomes::DESERT
+            } else {
+                biomes::BEACH
+            }
+        } else if height > SEA_LEVEL + 40 {
+            biomes::MOUNTAINS
+        } else if temperature < COLD {
+            biomes::SNOWY_TUNDRA
+        } else if dry_heat {
+            biomes::DESERT
+        } else if humidity > 0.15 {
+            if temperature < 0.0 {
+                biomes::TAIGA
+            } else {
+                biomes::FOREST
+            }
+        } else {
+            biomes::PLAINS
+        };
+
+        Column { height, biome }
+    }
+
+    /// Block at the given depth below the surface, None for stone
+    fn surface(&self, column: Column, depth: usize) -> Option<BlockState> {
+        let blocks = &self.blocks;
+        match column.biome {
+            biomes::OCEAN | biomes::DEEP_OCEAN | biomes::FROZEN_OCEAN if depth < 3 => {
+                if column.height + 12 < SEA_LEVEL {
+                    Some(blocks.gravel)
+                } else {
+                    Some(blocks.sand)
+                }
+            }
+            biomes::BEACH | biomes::DESERT => match depth {
+                0..=2 => Some(blocks.sand),
+                3..=5 => Some(blocks.sandstone),
+                _ => None,
+            },
+            // bare peaks
+            biomes::MOUNTAINS if column.height > SEA_LEVEL + 60 => None,
+            biomes::SNOWY_TUNDRA if depth == 0 => Some(blocks.snowy_grass),
+            _ => match depth {
+                0 => Some(blocks.grass),
+                1..=3 => Some(blocks.dirt),
+                _ => None,
+            },
+        }
+    }
+
+    fn fill_column(
+        &self,
+        chunk: &mut ChunkColumn,
+        (x, z): (usize, usize),
+        column: Column,
+        random: &mut Random,
+    ) {
+        for y in 0..=column.height.max(SEA_LEVEL) {
+            let block =
+                if y < BEDROCK_LAYERS && random.next_below(BEDROCK_LAYERS as u32) >= y as u32 {
+                    self.blocks.bedrock
+                } else if y > column.height {
+                    self.blocks.water
+                } else {
+                    self.surface(column, column.height - y)
+                        .unwrap_or(self.blocks.stone)
+                };
+
+            chunk.set_block(x, y, z, block);
+        }
+    }
+
+    fn carve_caves(&self, chunk: &mut ChunkColumn, columns: &[Column]) {
+        let pos = chunk.pos();
+        for x in 0..16 {
+            for z in 0..16 {
+                let column = columns[x * 16 + z];
+                // keep a roof under water so caves don't flood
+                let top = if column.height <= SEA_LEVEL + 1 {
+                    column.height.saturating_sub(4)
+                } else {
+                    column.height
+                };
+
+                let (wx, wz) = (
+                    (pos.x * 16 + x as i32) as f64 / CAVE_SCALE,
+                    (pos.z * 16 + z as i32) as f64 / CAVE_SCALE,
+                );
+                for y in BEDROCK_LAYERS..=top {
+                    let wy = y as f64 / CAVE_VERTICAL_SCALE;
+                    let a = self.caves.0.sample(wx, wy, wz);
+                    if a.abs() > CAVE_WIDTH {
+                        continue;
+                    }
+
+                    if self.caves.1.sample(wx, wy, wz).abs() <= CAVE_WIDTH {
+                        chunk.set_block(x, y, z, AIR);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Oak trees, kept inside the chunk so neighbours don't need generating
+    fn plant_trees(&self, chunk: &mut ChunkColumn, columns: &[Column], random: &mut Random) {
+        for _ in 0..16 {
+            let (x, z) = (
+                2 + random.next_below(12) as usize,
+                2 + random.next_below(12) as usize,
+            );
+            let column = columns[x * 16 + z];
+
+            // chance out of 16 per attempt
+            let density = match column.biome {
+                biomes::FOREST => 8,
+                biomes::TAIGA => 4,
+                biomes::PLAINS | biomes::MOUNTAINS => 1,
+                _ => 0,
+            };
+            if random.next_below(16) >= density
+                || chunk.block(x, column.height, z) != self.blocks.grass
+            {
+                continue;
+            }
+
+            let base = column.height + 1;
+            let trunk = 4 + random.next_below(3) as usize;
+            for dy in trunk - 3..=trunk {
+                let radius: isize = if dy + 1 >= trunk { 1 } else { 2 };
+                for dx in -radius..=radius {
+                    for dz in -radius..=radius {
+                        // rounded corners, sometimes missing on the lower layers
+                        let corner = dx.abs() == radius && dz.abs() == radius;
+                        if corner && (radius == 1 || random.next_below(2) == 0) {
+                            continue;
+                        }
+
+                        let (lx, lz) = ((x as isize + dx) as usize, (z as isize + dz) as usize);
+                        if chunk.block(lx, base + dy, lz) == AIR {
+                            chunk.set_block(lx, base + dy, lz, self.blocks.leaves);
+                        }
+                    }
+                }
+            }
+            for dy in 0..trunk {
+                chunk.set_block(x, base + dy, z, self.blocks.log);
+            }
+            chunk.set_block(x, column.height, z, self.blocks.dirt);
+        }
+    }
+
+    /// Different for each chunk but the same every time it's generated
+    fn chunk_random(&self, pos: ChunkPos) -> Random {
+        Random::new(
+            self.seed
+                ^ (pos.x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+                ^ (pos.z as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f),
+        )
+    }
+}
+
+impl ChunkGenerator for OverworldGenerator {
+    fn generate(&self, pos: ChunkPos) -> ChunkColumn {
+        let mut chunk = ChunkColumn::new(pos);
+        let mut random = self.chunk_random(pos);
+
+        let mut columns = Vec::with_capacity(16 * 16);
+        for x in 0..16 {
+            for z in 0..16 {
+                let column = self.column(pos.x * 16 + x as i32, pos.z * 16 + z as i32);
+                self.fill_column(&mut chunk, (x, z), column, &mut random);
+                columns.push(column);
+            }
+        }
+
+        self.carve_caves(&mut chunk, &columns);
+        self.plant_trees(&mut chunk, &columns, &mut random);
+
+        // biomes are stored per 4x4x4 cell, indexed by y, z then x
+        let biomes = chunk.biomes_mut();
+        for (idx, biome) in biomes.iter_mut().enumerate() {
+            let (x, z) = (idx & 3, (idx >> 2) & 3);
+            *biome = columns[(x * 4 + 2) * 16 + z * 4 + 2].biome;
+        }
+
+        chunk
+    }
+
+    /// Nearest dry land to the origin
+    fn spawn_point(&self) -> (i32, i32, i32) {
+        let mut candidates = Vec::new();
+        for x in (-SPAWN_SEARCH_RADIUS..=SPAWN_SEARCH_RADIUS).step_by(16) {
+            for z in (-SPAWN_SEARCH_RADIUS..=SPAWN_SEARCH_RADIUS).step_by(16) {
+                candidates.push((x, z));
+            }
+        }
+        candidates.sort_by_key(|(x, z)| x * x + z * z);
+
+        let land = candidates.into_iter().find_map(|(x, z)| {
+            let column = self.column(x, z);
+            match column.biome {
+                biomes::PLAINS
+                | biomes::FOREST
+                | biomes::TAIGA
+                | biomes::DESERT
+                | biomes::SNOWY_TUNDRA => Some((x, column.height as i32 + 1, z)),
+                _ => None,
+            }
+        });
+
+        land.unwrap_or_else(|| (0, self.column(0, 0).height.max(SEA_LEVEL) as i32 + 1, 0))
+    }
+
+    fn name(&self) -> &'static str {
+        "default"
+    }
+}
+
+impl Blocks {
+    fn new(registry: &BlockRegistry) -> McResult<Self> {
+        let state = |name: &str, properties: &[(&str, &str)]| {
+            let properties: Properties = properties
+                .iter()
+                .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+                .collect();
+            registry
+                .state(name, &properties)
+                .ok_or_else(|| McError::BadGeneratorSettings(format!("unknown block {}", name)))
+        };
+
+        Ok(Self {
+            stone: state("minecraft:stone", &[])?,
+            dirt: state("minecraft:dirt", &[])?,
+            grass: state("minecraft:grass_block", &[("snowy", "false")])?,
+            snowy_grass: state("minecraft:grass_block", &[("snowy", "true")])?,
+            sand: state("minecraft:sand", &[])?,
+            sandstone: state("minecraft:sandstone", &[])?,
+            gravel: state("minecraft:gravel", &[])?,
+            water: state("minecraft:water", &[("level", "0")])?,
+            bedrock: state("minecraft:bedrock", &[])?,
+            log: state("minecraft:oak_log", &[("axis", "y")])?,
+            leaves: state(
+                "minecraft:oak_leaves",
+                &[("distance", "1"), ("persistent", "true")],
+            )?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::blocks;
+
+    #[test]
+    fn deterministic_terrain() {
+        let registry = BlockRegistry::load("/nonexistent".as_ref()).unwrap();
+        let generator = OverworldGenerator::new(1234, &registry).unwrap();
+        let pos = ChunkPos::new(3, -7);
+
+        let (a, b) = (generator.generate(pos), generator.generate(pos));
+        let other = OverworldGenerator::new(5678, &registry)
+            .unwrap()
+            .generate(pos);
+        let blocks_of = |chunk: &ChunkColumn| {
+            (0..16 * 16 * CHUNK_HEIGHT)
+                .map(|i| chunk.block(i & 15, i >> 8, (i >> 4) & 15))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(blocks_of(&a), blocks_of(&b));
+        assert_ne!(blocks_of(&a), blocks_of(&other));
+        assert_eq!(a.biomes(), b.biomes());
+
+        assert_eq!(a.block(0, 0, 0), blocks::BEDROCK);
+        assert_eq!(a.block(0, CHUNK_HEIGHT - 1, 0), AIR);
+
+        let (x, y, z) = generator.spawn_point();
+        let column = generator.column(x, z);
+        assert_eq!(y as usize, column.height + 1);
+        assert!(column.height > SEA_LEVEL);
+    }
+}
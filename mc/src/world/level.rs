@@ -0,0 +1,134 @@
+//! `level.dat`, the world's global settings
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{McError, McResult};
+use crate::nbt::{self, Tag};
+use crate::world::anvil::DATA_VERSION;
+
+pub const LEVEL_FILE: &str = "level.dat";
+
+/// Anvil format version
+const STORAGE_VERSION: i32 = 19133;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelData {
+    pub name: String,
+    pub seed: i64,
+    /// Block position players spawn at
+    pub spawn: (i32, i32, i32),
+    /// Total ticks the world has run for
+    pub time: i64,
+    /// Ticks into the day/night cycle, not wrapped
+    pub day_time: i64,
+    /// Level type the world was created with
+    pub generator_name: String,
+    /// Settings of the generator, only used by flat worlds
+    pub generator_options: Option<Tag>,
+}
+
+impl LevelData {
+    pub fn load(path: &Path) -> McResult<Self> {
+        Self::from_tag(&read(path)?)
+    }
+
+    pub fn from_tag(root: &Tag) -> McResult<Self> {
+        let data = root
+            .get("Data")
+            .ok_or_else(|| McError::BadLevel("missing Data".to_owned()))?;
+        let int = |name: &str| {
+            data.get(name)
+                .and_then(Tag::as_int)
+                .ok_or_else(|| McError::BadLevel(format!("missing {}", name)))
+        };
+        let long = |name: &str| data.get(name).and_then(Tag::as_long).unwrap_or(0);
+
+        Ok(Self {
+            name: data
+                .get("LevelName")
+                .and_then(Tag::as_str)
+                .unwrap_or_default()
+                .to_owned(),
+            seed: long("RandomSeed"),
+            spawn: (int("SpawnX")?, int("SpawnY")?, int("SpawnZ")?),
+            time: long("Time"),
+            day_time: long("DayTime"),
+            generator_name: data
+                .get("generatorName")
+                .and_then(Tag::as_str)
+                .unwrap_or("default")
+                .to_owned(),
+            generator_options: data.get("generatorOptions").cloned(),
+        })
+    }
+
+    /// Updates the root tag the level was loaded from, keeping the settings the server doesn't
+    /// use, or creates a new one
+    pub fn to_tag(&self, original: Option<&Tag>) -> Tag {
+        let mut root = original
+            .cloned()
+            .unwrap_or_else(|| Tag::Compound(Vec::new()));
+        if root.get("Data").and_then(Tag::as_compound).is_none() {
+            let mut data = vec![
+                ("LevelName".to_owned(), Tag::String(self.name.clone())),
+                ("version".to_owned(), Tag::Int(STORAGE_VERSION)),
+                ("DataVersion".to_owned(), Tag::Int(DATA_VERSION)),
+                ("initialized".to_owned(), Tag::Byte(1)),
+                (
+                    "generatorName".to_owned(),
+                    Tag::String(self.generator_name.clone()),
+                ),
+            ];
+            if let Some(options) = &self.generator_options {
+                data.push(("generatorOptions".to_owned(), options.clone()));
+            }
+            root.insert("Data", Tag::Compound(data));
+        }
+
+        let data = root.get_mut("Data").expect("just inserted");
+        let last_played = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        data.insert("RandomSeed", Tag::Long(self.seed));
+        data.insert("SpawnX", Tag::Int(self.spawn.0));
+        data.insert("SpawnY", Tag::Int(self.spawn.1));
+        data.insert("SpawnZ", Tag::Int(self.spawn.2));
+        data.insert("Time", Tag::Long(self.time));
+        data.insert("DayTime", Tag::Long(self.day_time));
+        data.insert("LastPlayed", Tag::Long(last_played));
+
+        root
+    }
+}
+
+/// Reads a gzipped root tag
+pub fn read(path: &Path) -> McResult<Tag> {
+    let (_, root) = nbt::read_gzip(&fs::read(path)?)?;
+    Ok(root)
+}
+
+/// Writes a gzipped root tag via a temporary file, keeping the previous one as `<name>_old` like
+/// vanilla
+pub fn write(path: &Path, root: &Tag) -> McResult<()> {
+    let data = nbt::write_gzip("", root)?;
+    let with_suffix = |suffix: &str| {
+        let mut name = path.file_name().unwrap_or_default().to_owned();
+        name.push(suffix);
+        path.with_file_name(name)
+    };
+    let (new, old) = (with_suffix("_new"), with_suffix("_old"));
+
+    let mut file = File::create(&new)?;
+    file.write_all(&data)?;
+    file.sync_all()?;
+
+    if path.exists() {
+        fs::rename(path, &old)?;
+    }
+    fs::rename(&new, path)?;
+    Ok(())
+}
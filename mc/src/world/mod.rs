@@ -0,0 +1,738 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_std::task;
+use futures::channel::{mpsc, oneshot};
+use futures::future::{self, Either};
+use futures::{stream, Future, FutureExt, Stream, StreamExt};
+use log::*;
+
+pub use chunk::{pack, unpack, BlockState, ChunkColumn, ChunkPos, ChunkSection, AIR, CHUNK_HEIGHT};
+pub use generator::{biomes, ChunkGenerator, FlatGenerator, GeneratorSettings, OverworldGenerator};
+pub use level::LevelData;
+pub use registry::{BlockRegistry, BLOCK_REPORT_PATH};
+
+use crate::error::{McError, McResult};
+use crate::nbt::Tag;
+use cache::ChunkCache;
+use region::{RegionFile, RegionPos};
+
+mod anvil;
+mod cache;
+mod chunk;
+mod generator;
+mod level;
+mod region;
+mod registry;
+
+/// Global palette IDs for 1.15.2
+pub mod blocks {
+    use super::BlockState;
+
+    pub const STONE: BlockState = 1;
+    pub const GRASS_BLOCK: BlockState = 9;
+    pub const DIRT: BlockState = 10;
+    pub const BEDROCK: BlockState = 33;
+}
+
+pub struct World {
+    spawn: (i32, i32, i32),
+
+    seed: i64,
+
+    /// Shared with the worker threads loading chunks
+    source: Arc<ChunkSource>,
+
+    /// Root tag of `level.dat`, None if the world is new
+    level: Option<Tag>,
+
+    /// Chunks changed since they were last saved
+    modified: HashMap<ChunkPos, LoadedChunk>,
+
+    /// Writes saves in the order they're made, None for the placeholder flat world
+    saver: Option<SaveQueue>,
+
+    /// Incremented by each save, to tell which save handed over a chunk
+    save_id: u64,
+
+    /// Total ticks the world has run for
+    age: i64,
+
+    /// Ticks into the day/night cycle, counting days for the moon phase
+    day_time: i64,
+}
+
+/// Where chunks that haven't been modified come from
+struct ChunkSource {
+    /// None for the placeholder flat world
+    storage: Option<Storage>,
+    /// Fills in chunks missing from storage
+    generator: Box<dyn ChunkGenerator>,
+    /// Chunks handed over to the saver along with the ID of the save, which are loaded from here
+    /// until they've been written. Those that fail to save stay here to be retried
+    saving: Mutex<HashMap<ChunkPos, (u64, LoadedChunk)>>,
+    /// Columns sent to clients recently, which may be sent again as they move around
+    cache: Mutex<ChunkCache>,
+}
+
+/// Files of a vanilla world
+struct Storage {
+    dir: PathBuf,
+    region_dir: PathBuf,
+    registry: BlockRegistry,
+    /// Opened on first use, None if the region doesn't exist. Each region has its own lock so
+    /// chunks in different regions can be read and written at once
+    regions: Mutex<HashMap<RegionPos, Option<Arc<Mutex<RegionFile>>>>>,
+}
+
+/// A chunk and where it came from
+#[derive(Clone)]
+struct LoadedChunk {
+    column: ChunkColumn,
+    /// Tag the chunk was loaded from, so entries the server doesn't understand are saved too
+    original: Option<Tag>,
+    /// Had unrecognised blocks or failed to load, so saving would destroy data
+    lossy: bool,
+}
+
+/// What a save writes, after the world has moved on
+struct SaveJob {
+    positions: Vec<ChunkPos>,
+    /// Root tag of `level.dat`
+    level: Tag,
+    age: i64,
+    timestamp: u32,
+}
+
+/// Saves waiting to be written, each with where to send the result
+type SaveQueue = mpsc::UnboundedSender<(SaveJob, oneshot::Sender<McResult<usize>>)>;
+
+/// Height of the top layer of the placeholder flat world
+const FLAT_SURFACE: usize = 63;
+
+/// Chunks loaded or generated at once for each request
+const CHUNK_WORKERS: usize = 4;
+
+/// Columns kept in memory after being sent, enough for a couple of players' views
+const CHUNK_CACHE_SIZE: usize = 1024;
+
+pub const TICKS_PER_DAY: i64 = 24_000;
+
+/// Morning
+const START_TIME_OF_DAY: i64 = 1_000;
+
+impl World {
+    /// Placeholder flat world that isn't saved
+    pub fn new() -> Self {
+        let layers = vec![
+            ("minecraft:bedrock".to_owned(), 1),
+            ("minecraft:stone".to_owned(), FLAT_SURFACE - 4),
+            ("minecraft:dirt".to_owned(), 3),
+            ("minecraft:grass_block".to_owned(), 1),
+        ];
+        let generator = FlatGenerator::new(layers, biomes::PLAINS, &BlockRegistry::builtin())
+            .expect("built in blocks are known");
+
+        Self::with_source(None, Box::new(generator))
+    }
+
+    fn with_source(storage: Option<Storage>, generator: Box<dyn ChunkGenerator>) -> Self {
+        let has_storage = storage.is_some();
+        let source = Arc::new(ChunkSource {
+            storage,
+            generator,
+            saving: Mutex::new(HashMap::new()),
+            cache: Mutex::new(ChunkCache::new(CHUNK_CACHE_SIZE)),
+        });
+
+        Self {
+            spawn: source.generator.spawn_point(),
+            seed: 0,
+            saver: if has_storage {
+                Some(spawn_saver(source.clone()))
+            } else {
+                None
+            },
+            source,
+            level: None,
+            modified: HashMap::new(),
+            save_id: 0,
+            age: 0,
+            day_time: START_TIME_OF_DAY,
+        }
+    }
+
+    /// Loads the vanilla world in the given directory, or creates a new one to be saved there if
+    /// there isn't one. Chunks missing from the region files are generated
+    pub fn load(dir: &Path, block_report: &Path, settings: &GeneratorSettings) -> McResult<Self> {
+        let registry = BlockRegistry::load(block_report)?;
+        let level_path = dir.join(level::LEVEL_FILE);
+
+        let (level, root) = if level_path.exists() {
+            let root = level::read(&level_path)?;
+            (Some(LevelData::from_tag(&root)?), Some(root))
+        } else {
+            (None, None)
+        };
+
+        let (generator, seed) = match &level {
+            Some(level) => {
+                info!("loaded world '{}' from {}", level.name, dir.display());
+                let generator = generator::saved_generator(
+                    &level.generator_name,
+                    level.generator_options.as_ref(),
+                    level.seed,
+                    &registry,
+                )?;
+                (generator, level.seed)
+            }
+            None => {
+                let seed = settings.seed();
+                let generator = settings.generator(seed, &registry)?;
+                info!(
+                    "creating a new '{}' world in {} with seed {}",
+                    generator.name(),
+                    dir.display(),
+                    seed
+                );
+                (generator, seed)
+            }
+        };
+
+        let storage = Storage {
+            dir: dir.to_owned(),
+            region_dir: dir.join("region"),
+            registry,
+            regions: Mutex::new(HashMap::new()),
+        };
+        let mut world = Self::with_source(Some(storage), generator);
+        world.seed = seed;
+        world.level = root;
+        if let Some(level) = level {
+            if is_valid_spawn(level.spawn) {
+                world.spawn = level.spawn;
+            } else {
+                warn!(
+                    "spawn point {:?} in level.dat is out of range, using {:?}",
+                    level.spawn, world.spawn
+                );
+            }
+            world.age = level.time;
+            world.day_time = level.day_time;
+        }
+
+        Ok(world)
+    }
+
+    /// Advances world time by a single tick
+    pub fn tick(&mut self) {
+        self.age += 1;
+        self.day_time += 1;
+    }
+
+    pub fn age(&self) -> i64 {
+        self.age
+    }
+
+    pub fn time_of_day(&self) -> i64 {
+        self.day_time.rem_euclid(TICKS_PER_DAY)
+    }
+
+    /// Block position players spawn at
+    pub fn spawn(&self) -> (i32, i32, i32) {
+        self.spawn
+    }
+
+    pub fn hashed_seed(&self) -> i64 {
+        hash_seed(self.seed)
+    }
+
+    /// Name of the generator, which the client uses for the sky and fog
+    pub fn level_type(&self) -> &'static str {
+        self.source.generator.name()
+    }
+
+    /// Loaded or generated on a worker thread if it hasn't been modified
+    pub async fn chunk(&self, pos: ChunkPos) -> ChunkColumn {
+        match self.modified.get(&pos) {
+            Some(chunk) => chunk.column.clone(),
+            None => {
+                let source = self.source.clone();
+                task::spawn_blocking(move || source.column(pos)).await
+            }
+        }
+    }
+
+    /// The chunks in the given order. Those that haven't been modified are loaded or generated on
+    /// worker threads, so the stream can be consumed without holding up the game. Each position is
+    /// only started if `wanted` still returns true for it
+    pub fn chunks(
+        &self,
+        positions: Vec<ChunkPos>,
+        mut wanted: impl FnMut(ChunkPos) -> bool + Send,
+    ) -> impl Stream<Item = ChunkColumn> + Send {
+        let mut modified: HashMap<_, _> = positions
+            .iter()
+            .filter_map(|pos| self.modified.get(pos).map(|c| (*pos, c.column.clone())))
+            .collect();
+        let source = self.source.clone();
+
+        stream::iter(positions)
+            .filter(move |pos| future::ready(wanted(*pos)))
+            .map(move |pos| match modified.remove(&pos) {
+                Some(column) => Either::Left(future::ready(column)),
+                None => {
+                    let source = source.clone();
+                    Either::Right(task::spawn_blocking(move || source.column(pos)))
+                }
+            })
+            .buffered(CHUNK_WORKERS)
+    }
+
+    /// Ignores positions outside of the world's height. The chunk is loaded on a worker thread if
+    /// it hasn't been modified already
+    pub async fn set_block(&mut self, x: i32, y: i32, z: i32, state: BlockState) {
+        if y < 0 || y >= CHUNK_HEIGHT as i32 {
+            return;
+        }
+
+        let pos = ChunkPos::new(x >> 4, z >> 4);
+        if !self.modified.contains_key(&pos) {
+            let source = self.source.clone();
+            let chunk = task::spawn_blocking(move || source.load(pos)).await;
+            if let Ok(mut cache) = lock(&self.source.cache) {
+                cache.remove(pos);
+            }
+            if chunk.lossy {
+                warn!(
+                    "chunk {:?} couldn't be fully loaded and won't be saved",
+                    pos
+                );
+            }
+            self.modified.insert(pos, chunk);
+        }
+
+        if let Some(chunk) = self.modified.get_mut(&pos) {
+            chunk
+                .column
+                .set_block((x & 0xf) as usize, y as usize, (z & 0xf) as usize, state);
+        }
+    }
+
+    /// Number of chunks waiting to be saved
+    pub fn modified_chunks(&self) -> usize {
+        self.modified.values().filter(|c| !c.lossy).count()
+    }
+
+    /// Hands modified chunks and `level.dat` over to be written on a worker thread, resolving to
+    /// how many chunks were saved. Chunks that fail to save are retried by the next save
+    pub fn save(&mut self) -> impl Future<Output = McResult<usize>> + Send + 'static {
+        let (saver, storage) = match (&self.saver, &self.source.storage) {
+            (Some(saver), Some(storage)) => (saver, storage),
+            _ => return Either::Left(future::ready(Ok(0))),
+        };
+
+        let positions = {
+            let mut saving = match lock(&self.source.saving) {
+                Ok(saving) => saving,
+                Err(e) => return Either::Left(future::ready(Err(e))),
+            };
+
+            self.save_id += 1;
+            let (lossy, changed): (HashMap<_, _>, HashMap<_, _>) =
+                std::mem::take(&mut self.modified)
+                    .into_iter()
+                    .partition(|(_, chunk)| chunk.lossy);
+            self.modified = lossy;
+            for (pos, chunk) in changed {
+                saving.insert(pos, (self.save_id, chunk));
+            }
+            saving.keys().copied().collect()
+        };
+
+        let level = LevelData {
+            name: storage
+                .dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            seed: self.seed,
+            spawn: self.spawn,
+            time: self.age,
+            day_time: self.day_time,
+            generator_name: self.source.generator.name().to_owned(),
+            generator_options: self.source.generator.options(),
+        };
+        let root = level.to_tag(self.level.as_ref());
+        self.level = Some(root.clone());
+
+        let job = SaveJob {
+            positions,
+            level: root,
+            age: self.age,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as u32)
+                .unwrap_or(0),
+        };
+        let (done, result) = oneshot::channel();
+        if saver.unbounded_send((job, done)).is_err() {
+            return Either::Left(future::ready(Err(McError::StoragePanicked)));
+        }
+
+        Either::Right(result.map(|result| result.unwrap_or(Err(McError::StoragePanicked))))
+    }
+}
+
+impl ChunkSource {
+    /// From storage if it's there, otherwise generated
+    fn load(&self, pos: ChunkPos) -> LoadedChunk {
+        match self.read(pos) {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => LoadedChunk {
+                column: self.generator.generate(pos),
+                original: None,
+                lossy: false,
+            },
+            Err(e) => {
+                warn!("failed to load chunk {:?}: {}", pos, e);
+                LoadedChunk {
+                    column: self.generator.generate(pos),
+                    original: None,
+                    lossy: true,
+                }
+            }
+        }
+    }
+
+    /// For sending, from the cache if it was used recently
+    fn column(&self, pos: ChunkPos) -> ChunkColumn {
+        if let Some(column) = lock(&self.cache).ok().and_then(|mut cache| cache.get(pos)) {
+            return column;
+        }
+
+        let column = self.load(pos).column;
+        if let Ok(mut cache) = lock(&self.cache) {
+            cache.insert(column.clone());
+        }
+        column
+    }
+
+    fn read(&self, pos: ChunkPos) -> McResult<Option<LoadedChunk>> {
+        if let Some((_, chunk)) = lock(&self.saving)?.get(&pos) {
+            return Ok(Some(chunk.clone()));
+        }
+
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Ok(None),
+        };
+
+        match storage.read_chunk(pos)? {
+            Some(tag) => {
+                let (column, lossy) = anvil::read_chunk(&tag, pos, &storage.registry)?;
+                Ok(Some(LoadedChunk {
+                    column,
+                    original: Some(tag),
+                    lossy,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Runs on a worker thread
+    fn save(&self, job: SaveJob) -> McResult<usize> {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Ok(0),
+        };
+
+        let mut saved = 0;
+        let mut first_error = None;
+        for pos in job.positions {
+            match self.save_chunk(storage, pos, job.age, job.timestamp) {
+                Ok(true) => saved += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    warn!("failed to save chunk {:?}: {}", pos, e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        std::fs::create_dir_all(&storage.dir)?;
+        level::write(&storage.dir.join(level::LEVEL_FILE), &job.level)?;
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(saved),
+        }
+    }
+
+    /// Writes a chunk that was handed over for saving, returning false if an earlier save already
+    /// wrote it
+    fn save_chunk(
+        &self,
+        storage: &Storage,
+        pos: ChunkPos,
+        age: i64,
+        timestamp: u32,
+    ) -> McResult<bool> {
+        let (id, chunk) = match lock(&self.saving)?.get(&pos) {
+            Some((id, chunk)) => (*id, chunk.clone()),
+            None => return Ok(false),
+        };
+
+        storage.save_chunk(&chunk, age, timestamp)?;
+
+        // a newer version may have been handed over while this one was written
+        let mut saving = lock(&self.saving)?;
+        if matches!(saving.get(&pos), Some((saving_id, _)) if *saving_id == id) {
+            saving.remove(&pos);
+            // in case an older version was loaded while this one was modified
+            lock(&self.cache)?.remove(pos);
+        }
+        Ok(true)
+    }
+}
+
+/// Spawns the task running each save in turn on a worker thread, so they're written in order
+fn spawn_saver(source: Arc<ChunkSource>) -> SaveQueue {
+    let (saver, mut jobs): (SaveQueue, _) = mpsc::unbounded();
+    task::spawn(async move {
+        while let Some((job, done)) = jobs.next().await {
+            let source = source.clone();
+            let result = task::spawn_blocking(move || source.save(job)).await;
+            // autosaves aren't waited for
+            let _ = done.send(result);
+        }
+    });
+
+    saver
+}
+
+impl Storage {
+    fn read_chunk(&self, pos: ChunkPos) -> McResult<Option<Tag>> {
+        match self.region(RegionPos::containing(pos))? {
+            Some(region) => lock(&region)?.read_chunk(pos),
+            None => Ok(None),
+        }
+    }
+
+    fn save_chunk(&self, chunk: &LoadedChunk, age: i64, timestamp: u32) -> McResult<()> {
+        let pos = chunk.column.pos();
+        let tag = anvil::write_chunk(&chunk.column, chunk.original.as_ref(), &self.registry, age)?;
+
+        let region_pos = RegionPos::containing(pos);
+        let region = match self.region(region_pos)? {
+            Some(region) => region,
+            None => self.create_region(region_pos)?,
+        };
+        let mut region = lock(&region)?;
+        region.write_chunk(pos, &tag, timestamp)
+    }
+
+    /// Opens the region on first use, without holding the lock on the other regions
+    fn region(&self, region_pos: RegionPos) -> McResult<Option<Arc<Mutex<RegionFile>>>> {
+        if let Some(region) = lock(&self.regions)?.get(&region_pos) {
+            return Ok(region.clone());
+        }
+
+        let path = region_pos.path(&self.region_dir);
+        let region = if path.exists() {
+            debug!("opening region {}", path.display());
+            Some(Arc::new(Mutex::new(RegionFile::open(&path)?)))
+        } else {
+            None
+        };
+
+        // another thread may have got there first
+        let mut regions = lock(&self.regions)?;
+        Ok(regions.entry(region_pos).or_insert(region).clone())
+    }
+
+    /// Creates a missing region, keeping the others locked so it can't be created twice
+    fn create_region(&self, region_pos: RegionPos) -> McResult<Arc<Mutex<RegionFile>>> {
+        let mut regions = lock(&self.regions)?;
+        if let Some(Some(region)) = regions.get(&region_pos) {
+            return Ok(region.clone());
+        }
+
+        let path = region_pos.path(&self.region_dir);
+        debug!("creating region {}", path.display());
+        let region = Arc::new(Mutex::new(RegionFile::create(&path)?));
+        regions.insert(region_pos, Some(region.clone()));
+        Ok(region)
+    }
+}
+
+/// Locks storage shared with the worker threads, which is unusable if one of them panicked
+fn lock<T>(mutex: &Mutex<T>) -> McResult<MutexGuard<'_, T>> {
+    mutex.lock().map_err(|_| McError::StoragePanicked)
+}
+
+/// Whether the position fits in the 26/12/26 bits it's sent to clients as
+fn is_valid_spawn((x, y, z): (i32, i32, i32)) -> bool {
+    const HORIZONTAL: i32 = 1 << 25;
+    const VERTICAL: i32 = 1 << 11;
+    (-HORIZONTAL..HORIZONTAL).contains(&x)
+        && (-VERTICAL..VERTICAL).contains(&y)
+        && (-HORIZONTAL..HORIZONTAL).contains(&z)
+}
+
+/// First 8 bytes of the SHA-256 of the seed, which the client uses for biome noise
+pub fn hash_seed(seed: i64) -> i64 {
+    let hash = openssl::sha::sha256(&seed.to_le_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    i64::from_le_bytes(bytes)
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_round_trip() {
+        let dir = std::env::temp_dir().join(format!("mc-save-round-trip-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let no_report = Path::new("/nonexistent");
+        let settings = GeneratorSettings {
+            level_type: "flat".to_owned(),
+            ..GeneratorSettings::default()
+        };
+
+        task::block_on(async {
+            let mut world = World::load(&dir, no_report, &settings).unwrap();
+            assert_eq!(world.spawn(), (0, 4, 0));
+            world.set_block(-20, 100, 5, blocks::DIRT).await;
+            world.set_block(-20, 3, 6, AIR).await;
+            world.tick();
+            assert_eq!(world.modified_chunks(), 1);
+            assert_eq!(world.save().await.unwrap(), 1);
+            assert_eq!(world.modified_chunks(), 0);
+
+            // the generator is restored from level.dat rather than the settings
+            let overworld = GeneratorSettings::default();
+            let mut world = World::load(&dir, no_report, &overworld).unwrap();
+            assert_eq!(world.age(), 1);
+            assert_eq!(world.level_type(), "flat");
+            let pos = ChunkPos::new(-2, 0);
+            let chunk = world.chunk(pos).await;
+            assert_eq!(chunk.block(12, 100, 5), blocks::DIRT);
+            assert_eq!(chunk.block(12, 3, 6), AIR);
+            assert_eq!(chunk.block(12, 3, 7), blocks::GRASS_BLOCK);
+            assert_eq!(
+                world.chunk(ChunkPos::new(5, 5)).await.block(0, 3, 0),
+                blocks::GRASS_BLOCK
+            );
+
+            // modifying a saved chunk keeps earlier changes
+            world.set_block(-19, 101, 5, blocks::STONE).await;
+            let save = world.save();
+            // served from memory until it's been written
+            assert_eq!(world.chunk(pos).await.block(13, 101, 5), blocks::STONE);
+            save.await.unwrap();
+            assert_eq!(world.chunk(pos).await.block(13, 101, 5), blocks::STONE);
+
+            let chunk = World::load(&dir, no_report, &overworld)
+                .unwrap()
+                .chunk(pos)
+                .await;
+            assert_eq!(chunk.block(12, 100, 5), blocks::DIRT);
+            assert_eq!(chunk.block(13, 101, 5), blocks::STONE);
+        });
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unknown_states_are_never_saved() {
+        let dir = std::env::temp_dir().join(format!("mc-unknown-states-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // a sideways log, which the built-in blocks don't have
+        let pos = ChunkPos::new(0, 0);
+        let palette = vec![
+            Tag::Compound(vec![(
+                "Name".to_owned(),
+                Tag::String("minecraft:air".to_owned()),
+            )]),
+            Tag::Compound(vec![
+                (
+                    "Name".to_owned(),
+                    Tag::String("minecraft:oak_log".to_owned()),
+                ),
+                (
+                    "Properties".to_owned(),
+                    Tag::Compound(vec![("axis".to_owned(), Tag::String("x".to_owned()))]),
+                ),
+            ]),
+        ];
+        let indices = (0..chunk::SECTION_VOLUME).map(|i| if i == 0 { 1 } else { 0 });
+        let root = Tag::Compound(vec![(
+            "Level".to_owned(),
+            Tag::Compound(vec![
+                ("xPos".to_owned(), Tag::Int(pos.x)),
+                ("zPos".to_owned(), Tag::Int(pos.z)),
+                (
+                    "Sections".to_owned(),
+                    Tag::List(vec![Tag::Compound(vec![
+                        ("Y".to_owned(), Tag::Byte(0)),
+                        ("Palette".to_owned(), Tag::List(palette)),
+                        ("BlockStates".to_owned(), Tag::LongArray(pack(indices, 4))),
+                    ])]),
+                ),
+            ]),
+        )]);
+        let region_path = RegionPos::containing(pos).path(&dir.join("region"));
+        RegionFile::create(&region_path)
+            .and_then(|mut region| region.write_chunk(pos, &root, 0))
+            .unwrap();
+
+        task::block_on(async {
+            let settings = GeneratorSettings::default();
+            let mut world = World::load(&dir, Path::new("/nonexistent"), &settings).unwrap();
+            world.set_block(1, 100, 1, blocks::DIRT).await;
+            assert_eq!(world.save().await.unwrap(), 0);
+        });
+
+        let saved = RegionFile::open(&region_path)
+            .and_then(|mut region| region.read_chunk(pos))
+            .unwrap();
+        assert_eq!(saved, Some(root));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn chunk_stream() {
+        task::block_on(async {
+            let mut world = World::new();
+            let positions = vec![
+                ChunkPos::new(3, 3),
+                ChunkPos::new(1, 0),
+                ChunkPos::new(-1, 0),
+            ];
+            let chunks: Vec<_> = world.chunks(positions.clone(), |_| true).collect().await;
+            assert_eq!(chunks[1].block(1, 100, 0), AIR);
+
+            // the cached copy isn't used once it's modified
+            world.set_block(17, 100, 0, blocks::DIRT).await;
+            let chunks: Vec<_> = world.chunks(positions.clone(), |_| true).collect().await;
+            let received: Vec<_> = chunks.iter().map(ChunkColumn::pos).collect();
+            assert_eq!(received, positions);
+            assert_eq!(chunks[1].block(1, 100, 0), blocks::DIRT);
+            assert_eq!(chunks[2].block(1, FLAT_SURFACE, 0), blocks::GRASS_BLOCK);
+        });
+    }
+}
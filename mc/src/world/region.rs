@@ -0,0 +1,312 @@
+//! Anvil region files, each holding the compressed NBT of 32x32 chunks
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{McError, McResult};
+use crate::nbt::{self, Tag};
+use crate::world::ChunkPos;
+
+pub const SECTOR_SIZE: usize = 4096;
+
+/// Chunks along each side of a region
+pub const REGION_WIDTH: i32 = 32;
+
+const CHUNKS_PER_REGION: usize = (REGION_WIDTH * REGION_WIDTH) as usize;
+
+/// Location and timestamp tables
+const HEADER_SECTORS: u32 = 2;
+
+/// Sector counts are stored in a single byte
+const MAX_CHUNK_SECTORS: usize = 255;
+
+const COMPRESSION_GZIP: u8 = 1;
+const COMPRESSION_ZLIB: u8 = 2;
+const COMPRESSION_NONE: u8 = 3;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct RegionPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+pub struct RegionFile {
+    file: File,
+    /// Offset and length in sectors of each chunk, 0 if absent
+    locations: [u32; CHUNKS_PER_REGION],
+    /// Seconds since the epoch each chunk was last written
+    timestamps: [u32; CHUNKS_PER_REGION],
+}
+
+impl RegionPos {
+    pub fn containing(chunk: ChunkPos) -> Self {
+        Self {
+            x: chunk.x >> 5,
+            z: chunk.z >> 5,
+        }
+    }
+
+    /// e.g. `r.-1.2.mca`
+    pub fn path(self, region_dir: &Path) -> PathBuf {
+        region_dir.join(format!("r.{}.{}.mca", self.x, self.z))
+    }
+}
+
+impl RegionFile {
+    pub fn open(path: &Path) -> McResult<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        let mut header = vec![0u8; HEADER_SECTORS as usize * SECTOR_SIZE];
+        file.read_exact(&mut header)
+            .map_err(|e| McError::BadRegion(format!("{}: {}", path.display(), e)))?;
+
+        let (location_bytes, timestamp_bytes) = header.split_at(SECTOR_SIZE);
+        let mut locations = [0u32; CHUNKS_PER_REGION];
+        let mut timestamps = [0u32; CHUNKS_PER_REGION];
+        read_table(location_bytes, &mut locations);
+        read_table(timestamp_bytes, &mut timestamps);
+
+        Ok(Self {
+            file,
+            locations,
+            timestamps,
+        })
+    }
+
+    /// Creates an empty region file, replacing any existing one
+    pub fn create(path: &Path) -> McResult<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(&vec![0u8; HEADER_SECTORS as usize * SECTOR_SIZE])?;
+
+        Ok(Self {
+            file,
+            locations: [0; CHUNKS_PER_REGION],
+            timestamps: [0; CHUNKS_PER_REGION],
+        })
+    }
+
+    /// The chunk's root tag, or None if it hasn't been generated
+    pub fn read_chunk(&mut self, chunk: ChunkPos) -> McResult<Option<Tag>> {
+        let location = self.locations[chunk_index(chunk)];
+        let (offset, sectors) = (location >> 8, location & 0xff);
+        if location == 0 {
+            return Ok(None);
+        }
+        if offset < HEADER_SECTORS || sectors == 0 {
+            return Err(McError::BadRegion(format!(
+                "chunk {:?} at invalid sector {}",
+                chunk, offset
+            )));
+        }
+
+        self.file
+            .seek(SeekFrom::Start(offset as u64 * SECTOR_SIZE as u64))?;
+        let mut header = [0u8; 5];
+        self.file.read_exact(&mut header)?;
+
+        // length includes the compression type
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        if length == 0 || length + 4 > sectors as usize * SECTOR_SIZE {
+            return Err(McError::BadRegion(format!(
+                "chunk {:?} has invalid length {}",
+                chunk, length
+            )));
+        }
+
+        let mut data = vec![0u8; length - 1];
+        self.file.read_exact(&mut data)?;
+        let (_, tag) = match header[4] {
+            COMPRESSION_GZIP => nbt::read_gzip(&data)?,
+            COMPRESSION_ZLIB => nbt::read_zlib(&data)?,
+            COMPRESSION_NONE => Tag::read_named(&mut data.as_slice())?,
+            other => {
+                return Err(McError::BadRegion(format!(
+                    "unknown compression type {}",
+                    other
+                )))
+            }
+        };
+
+        Ok(Some(tag))
+    }
+
+    /// Writes the chunk's root tag zlib compressed into free sectors, which the old copy's are
+    /// once the header points at the new one
+    pub fn write_chunk(&mut self, chunk: ChunkPos, root: &Tag, timestamp: u32) -> McResult<()> {
+        let compressed = nbt::write_zlib("", root)?;
+
+        // length includes the compression type
+        let length = compressed.len() + 1;
+        // rounded up, including the length itself
+        let sectors = 1 + (length + 3) / SECTOR_SIZE;
+        if sectors > MAX_CHUNK_SECTORS {
+            return Err(McError::BadRegion(format!(
+                "chunk {:?} is too large at {} bytes",
+                chunk, length
+            )));
+        }
+
+        let idx = chunk_index(chunk);
+        let offset = self.allocate(sectors as u32);
+
+        let mut data = Vec::with_capacity(sectors * SECTOR_SIZE);
+        data.extend_from_slice(&(length as u32).to_be_bytes());
+        data.push(COMPRESSION_ZLIB);
+        data.extend_from_slice(&compressed);
+        data.resize(sectors * SECTOR_SIZE, 0);
+
+        self.file
+            .seek(SeekFrom::Start(offset as u64 * SECTOR_SIZE as u64))?;
+        self.file.write_all(&data)?;
+
+        // header last, so a failed write leaves the old chunk in place
+        let location = (offset << 8) | sectors as u32;
+        self.locations[idx] = location;
+        self.timestamps[idx] = timestamp;
+        self.file.seek(SeekFrom::Start(idx as u64 * 4))?;
+        self.file.write_all(&location.to_be_bytes())?;
+        self.file
+            .seek(SeekFrom::Start((SECTOR_SIZE + idx * 4) as u64))?;
+        self.file.write_all(&timestamp.to_be_bytes())?;
+
+        Ok(())
+    }
+
+    /// Offset of the first run of free sectors long enough
+    fn allocate(&self, sectors: u32) -> u32 {
+        let mut used: Vec<(u32, u32)> = self
+            .locations
+            .iter()
+            .filter(|location| **location != 0)
+            .map(|location| (location >> 8, location & 0xff))
+            .collect();
+        used.sort_unstable();
+
+        let mut start = HEADER_SECTORS;
+        for (offset, count) in used {
+            if offset >= start + sectors {
+                break;
+            }
+            start = start.max(offset + count);
+        }
+
+        start
+    }
+}
+
+fn read_table(bytes: &[u8], table: &mut [u32; CHUNKS_PER_REGION]) {
+    for (entry, bytes) in table.iter_mut().zip(bytes.chunks_exact(4)) {
+        *entry = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+}
+
+/// Index into the header tables
+fn chunk_index(chunk: ChunkPos) -> usize {
+    let (x, z) = (
+        chunk.x.rem_euclid(REGION_WIDTH),
+        chunk.z.rem_euclid(REGION_WIDTH),
+    );
+    (x + z * REGION_WIDTH) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_chunks() {
+        let pos = ChunkPos::new(-31, 33);
+        let tag = Tag::Compound(vec![("xPos".to_owned(), Tag::Int(pos.x))]);
+        let compressed = nbt::write_zlib("", &tag).unwrap();
+
+        // single chunk in the first sector after the header
+        let mut region = vec![0u8; SECTOR_SIZE * 3];
+        let location: u32 = (HEADER_SECTORS << 8) | 1;
+        let idx = chunk_index(pos) * 4;
+        region[idx..idx + 4].copy_from_slice(&location.to_be_bytes());
+
+        let offset = SECTOR_SIZE * 2;
+        let length = compressed.len() as u32 + 1;
+        region[offset..offset + 4].copy_from_slice(&length.to_be_bytes());
+        region[offset + 4] = COMPRESSION_ZLIB;
+        region[offset + 5..offset + 5 + compressed.len()].copy_from_slice(&compressed);
+
+        let region_pos = RegionPos::containing(pos);
+        assert_eq!(region_pos, RegionPos { x: -1, z: 1 });
+        let dir = std::env::temp_dir().join(format!("mc-read-chunks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = region_pos.path(&dir);
+        std::fs::write(&path, region).unwrap();
+
+        let mut file = RegionFile::open(&path).unwrap();
+        assert_eq!(file.read_chunk(pos).unwrap(), Some(tag));
+        assert_eq!(file.read_chunk(ChunkPos::new(-32, 32)).unwrap(), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Incompressible chunk spanning roughly the given number of sectors
+    fn chunk_of_size(pos: ChunkPos, sectors: usize) -> Tag {
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let noise = (0..sectors * SECTOR_SIZE / 8)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as i64
+            })
+            .collect();
+        Tag::Compound(vec![
+            ("xPos".to_owned(), Tag::Int(pos.x)),
+            ("Noise".to_owned(), Tag::LongArray(noise)),
+        ])
+    }
+
+    #[test]
+    fn write_chunks() {
+        let dir = std::env::temp_dir().join(format!("mc-write-chunks-{}", std::process::id()));
+        let path = RegionPos { x: 0, z: 0 }.path(&dir);
+        let (a, b, c) = (
+            ChunkPos::new(0, 0),
+            ChunkPos::new(1, 0),
+            ChunkPos::new(2, 0),
+        );
+
+        let mut file = RegionFile::create(&path).unwrap();
+        file.write_chunk(a, &chunk_of_size(a, 1), 100).unwrap();
+        file.write_chunk(b, &chunk_of_size(b, 1), 100).unwrap();
+        assert_eq!(file.locations[chunk_index(a)] >> 8, HEADER_SECTORS);
+        let b_location = file.locations[chunk_index(b)];
+
+        // a outgrows its sectors and moves to the end, leaving a gap c fits in
+        let grown = chunk_of_size(a, 3);
+        file.write_chunk(a, &grown, 200).unwrap();
+        assert!(file.locations[chunk_index(a)] >> 8 > b_location >> 8);
+        file.write_chunk(c, &chunk_of_size(c, 0), 300).unwrap();
+        assert_eq!(file.locations[chunk_index(c)] >> 8, HEADER_SECTORS);
+
+        // rewrites never overlap the sectors they replace
+        let old_offset = b_location >> 8;
+        file.write_chunk(b, &chunk_of_size(b, 1), 400).unwrap();
+        let b_location = file.locations[chunk_index(b)];
+        assert_ne!(b_location >> 8, old_offset);
+
+        let mut file = RegionFile::open(&path).unwrap();
+        assert_eq!(file.read_chunk(a).unwrap(), Some(grown));
+        assert_eq!(file.read_chunk(b).unwrap(), Some(chunk_of_size(b, 1)));
+        assert_eq!(file.read_chunk(c).unwrap(), Some(chunk_of_size(c, 0)));
+        assert_eq!(file.locations[chunk_index(b)], b_location);
+        assert_eq!(file.timestamps[chunk_index(a)], 200);
+        assert_eq!(file.timestamps[chunk_index(c)], 300);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,235 @@
+//! Mapping between block state names, as stored in region files, and global palette IDs
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use log::*;
+use serde::Deserialize;
+
+use crate::error::{McError, McResult};
+use crate::world::{blocks, BlockState, AIR};
+
+/// Block properties, e.g. `axis=y`
+pub type Properties = BTreeMap<String, String>;
+
+/// Where the vanilla data generator writes its block report, with
+/// `java -cp server.jar net.minecraft.data.Main --reports`
+pub const BLOCK_REPORT_PATH: &str = "generated/reports/blocks.json";
+
+/// Block name, property values and state ID
+type StateEntry = (
+    &'static str,
+    &'static [(&'static str, &'static str)],
+    BlockState,
+);
+
+/// Default states used when there's no block report, enough for simple worlds. Blocks with more
+/// than one state have their default properties, as vanilla writes them
+const BUILTIN_BLOCKS: &[StateEntry] = &[
+    ("minecraft:air", &[], AIR),
+    ("minecraft:stone", &[], blocks::STONE),
+    ("minecraft:granite", &[], 2),
+    ("minecraft:polished_granite", &[], 3),
+    ("minecraft:diorite", &[], 4),
+    ("minecraft:polished_diorite", &[], 5),
+    ("minecraft:andesite", &[], 6),
+    ("minecraft:polished_andesite", &[], 7),
+    (
+        "minecraft:grass_block",
+        &[("snowy", "false")],
+        blocks::GRASS_BLOCK,
+    ),
+    ("minecraft:dirt", &[], blocks::DIRT),
+    ("minecraft:coarse_dirt", &[], 11),
+    ("minecraft:podzol", &[], 13),
+    ("minecraft:cobblestone", &[], 14),
+    ("minecraft:oak_planks", &[], 15),
+    ("minecraft:spruce_planks", &[], 16),
+    ("minecraft:birch_planks", &[], 17),
+    ("minecraft:jungle_planks", &[], 18),
+    ("minecraft:acacia_planks", &[], 19),
+    ("minecraft:dark_oak_planks", &[], 20),
+    ("minecraft:bedrock", &[], blocks::BEDROCK),
+    ("minecraft:water", &[("level", "0")], 34),
+    ("minecraft:lava", &[("level", "0")], 50),
+    ("minecraft:sand", &[], 66),
+    ("minecraft:red_sand", &[], 67),
+    ("minecraft:gravel", &[], 68),
+    ("minecraft:gold_ore", &[], 69),
+    ("minecraft:iron_ore", &[], 70),
+    ("minecraft:coal_ore", &[], 71),
+    ("minecraft:oak_log", &[("axis", "y")], 73),
+    (
+        "minecraft:oak_leaves",
+        &[("distance", "7"), ("persistent", "false")],
+        157,
+    ),
+    ("minecraft:sandstone", &[], 245),
+];
+
+/// Non-default states used by the world generators
+const BUILTIN_STATES: &[StateEntry] = &[
+    ("minecraft:grass_block", &[("snowy", "true")], 8),
+    (
+        "minecraft:oak_leaves",
+        &[("distance", "1"), ("persistent", "true")],
+        144,
+    ),
+];
+
+pub struct BlockRegistry {
+    /// States of each block, the default first
+    by_name: HashMap<String, Vec<(Properties, BlockState)>>,
+    /// Indexed by state ID
+    by_id: Vec<Option<(String, Properties)>>,
+}
+
+#[derive(Deserialize)]
+struct ReportBlock {
+    states: Vec<ReportState>,
+}
+
+#[derive(Deserialize)]
+struct ReportState {
+    id: BlockState,
+    #[serde(default)]
+    default: bool,
+    #[serde(default)]
+    properties: Properties,
+}
+
+impl BlockRegistry {
+    /// Loads the vanilla block report, falling back to a few built-in blocks if it's missing
+    pub fn load(path: &Path) -> McResult<Self> {
+        match fs::read_to_string(path) {
+            Ok(json) => {
+                info!("loading block states from {}", path.display());
+                Self::from_report(&json)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                warn!(
+                    "no block report at {}, only a few blocks will be recognised",
+                    path.display()
+                );
+                Ok(Self::builtin())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn from_report(json: &str) -> McResult<Self> {
+        let report: HashMap<String, ReportBlock> =
+            serde_json::from_str(json).map_err(|e| McError::BadBlockReport(e.to_string()))?;
+
+        let mut registry = Self::empty();
+        for (name, mut block) in report {
+            block.states.sort_by_key(|s| !s.default);
+            for state in block.states {
+                registry.insert(&name, state.properties, state.id);
+            }
+        }
+
+        Ok(registry)
+    }
+
+    pub(crate) fn builtin() -> Self {
+        let mut registry = Self::empty();
+        for (name, properties, id) in BUILTIN_BLOCKS.iter().chain(BUILTIN_STATES) {
+            let properties = properties
+                .iter()
+                .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+                .collect();
+            registry.insert(name, properties, *id);
+        }
+
+        // indistinguishable from air to the client
+        registry.by_name.insert(
+            "minecraft:cave_air".to_owned(),
+            vec![(Properties::new(), AIR)],
+        );
+        registry.by_name.insert(
+            "minecraft:void_air".to_owned(),
+            vec![(Properties::new(), AIR)],
+        );
+        registry
+    }
+
+    fn empty() -> Self {
+        Self {
+            by_name: HashMap::new(),
+            by_id: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, name: &str, properties: Properties, id: BlockState) {
+        let idx = id as usize;
+        if self.by_id.len() <= idx {
+            self.by_id.resize(idx + 1, None);
+        }
+        self.by_id[idx] = Some((name.to_owned(), properties.clone()));
+
+        self.by_name
+            .entry(name.to_owned())
+            .or_default()
+            .push((properties, id));
+    }
+
+    /// State with exactly these properties, or the block's default state if none are given
+    pub fn state(&self, name: &str, properties: &Properties) -> Option<BlockState> {
+        let states = self.by_name.get(name)?;
+        states
+            .iter()
+            .find(|(props, _)| props == properties)
+            .or_else(|| states.first().filter(|_| properties.is_empty()))
+            .map(|(_, id)| *id)
+    }
+
+    /// Name and properties of a state
+    pub fn name(&self, state: BlockState) -> Option<(&str, &Properties)> {
+        self.by_id
+            .get(state as usize)
+            .and_then(Option::as_ref)
+            .map(|(name, props)| (name.as_str(), props))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lookup() {
+        let registry = BlockRegistry::from_report(
+            r#"{
+                "minecraft:oak_log": {
+                    "properties": {"axis": ["x", "y", "z"]},
+                    "states": [
+                        {"id": 72, "properties": {"axis": "x"}},
+                        {"id": 73, "properties": {"axis": "y"}, "default": true},
+                        {"id": 74, "properties": {"axis": "z"}}
+                    ]
+                },
+                "minecraft:air": {"states": [{"id": 0, "default": true}]}
+            }"#,
+        )
+        .unwrap();
+
+        let mut props = Properties::new();
+        props.insert("axis".to_owned(), "z".to_owned());
+        assert_eq!(registry.state("minecraft:oak_log", &props), Some(74));
+        assert_eq!(
+            registry.state("minecraft:oak_log", &Properties::new()),
+            Some(73)
+        );
+        assert_eq!(registry.state("minecraft:air", &Properties::new()), Some(0));
+        assert_eq!(registry.state("minecraft:stone", &props), None);
+        props.insert("axis".to_owned(), "w".to_owned());
+        assert_eq!(registry.state("minecraft:oak_log", &props), None);
+        props.insert("axis".to_owned(), "z".to_owned());
+
+        assert_eq!(registry.name(74), Some(("minecraft:oak_log", &props)));
+        assert_eq!(registry.name(1), None);
+    }
+}
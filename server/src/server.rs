@@ -1,16 +1,19 @@
-use async_std::io::{self};
+use async_std::io::{self, prelude::BufReadExt};
 use async_std::net::{TcpListener, TcpStream};
-use async_std::task;
+use async_std::{future, task};
 use futures::channel::mpsc::unbounded;
 use log::*;
 
 use async_std::sync::Arc;
 use futures::{pin_mut, select, FutureExt, SinkExt, StreamExt};
+use mc::config::ServerConfig;
 use mc::connection::PostPacketAction;
 use mc::connection::{ActiveComms, CommsRef, ConnectionState};
 use mc::error::{McError, McResult};
-use mc::game::{ClientMessage, ClientMessageSender, Game};
+use mc::game::{ClientMessage, ClientMessageSender, ConsoleCommandSender, Game};
 use mc::server::ServerData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 async fn handle_client(
     mut game_tx: ClientMessageSender,
@@ -28,8 +31,14 @@ async fn handle_client(
         ActiveComms::new(r, w)
     };
 
+    if let Some(ping) = reader.read_legacy_ping().await? {
+        debug!("responding to legacy ping from {:?}", peer);
+        let response = ping.response(&server_data).await;
+        return writer.send_legacy_response(&response).await;
+    }
+
     let comms = CommsRef::new(clientbound_tx.clone(), encryption);
-    let mut connection = ConnectionState::new(comms);
+    let mut connection = ConnectionState::new(comms, peer.ip());
 
     loop {
         let serverbound = async { reader.read_packet().await }.fuse();
@@ -71,13 +80,18 @@ async fn handle_client(
                 PostPacketAction::EnteredPlayState {
                     player_name,
                     player_uuid,
+                    connection,
+                    properties,
                 } => {
                     game_tx
                         .send((
                             player_uuid,
+                            connection,
                             ClientMessage::NewClient {
                                 outgoing: clientbound_tx.clone(),
                                 name: player_name,
+                                properties,
+                                address: peer.ip(),
                             },
                         ))
                         .await?;
@@ -87,23 +101,87 @@ async fn handle_client(
     }
 }
 
+/// Forwards lines typed into stdin to the game as commands
+async fn read_console(console_tx: ConsoleCommandSender) {
+    let mut lines = io::BufReader::new(io::stdin()).lines();
+    while let Some(line) = lines.next().await {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                error!("failed to read from console: {}", e);
+                break;
+            }
+        };
+
+        // slash is optional
+        let command = line.trim();
+        let command = command.strip_prefix('/').unwrap_or(command);
+        if command.is_empty() {
+            continue;
+        }
+
+        if console_tx.unbounded_send(command.to_owned()).is_err() {
+            // game has stopped
+            break;
+        }
+    }
+}
+
+/// Stops the server like the stop command on SIGINT/SIGTERM, exiting immediately on a second
+/// signal
+fn handle_signals(console_tx: ConsoleCommandSender) {
+    let signalled = AtomicBool::new(false);
+    let result = ctrlc::set_handler(move || {
+        if signalled.swap(true, Ordering::Relaxed)
+            || console_tx.unbounded_send("stop".to_owned()).is_err()
+        {
+            warn!("forcing exit");
+            std::process::exit(1);
+        }
+    });
+
+    if let Err(e) = result {
+        warn!("failed to install signal handler: {}", e);
+    }
+}
+
+/// How long to wait for connections to send their final packets when stopping
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs until the game stops
 async fn accept_clients(host: &str, port: u16, server_data: Arc<ServerData>) -> McResult<()> {
     let listener = TcpListener::bind((host, port)).await.map_err(McError::Io)?;
     info!("listening on {}:{}", host, port);
 
     // start game broker
     let (game_tx, game_rx) = unbounded();
+    let (console_tx, console_rx) = unbounded();
 
-    let game = Game::new(game_rx);
-    task::spawn(game.run());
+    let game = Game::new(game_rx, console_rx, &server_data)?;
+    let mut game = task::spawn(game.run()).fuse();
+    handle_signals(console_tx.clone());
+    task::spawn(read_console(console_tx));
+
+    // every connection task holds a sender, so the receiver ends once they've all finished
+    let (connections_tx, mut connections_rx) = unbounded::<()>();
 
     // start client loop
     let mut incoming = listener.incoming();
-    while let Some(stream) = incoming.next().await {
+    let result = loop {
+        let stream = select! {
+            stream = incoming.next().fuse() => match stream {
+                Some(stream) => stream,
+                None => break Ok(()),
+            },
+            result = game => break result,
+        };
+
         let server_data = server_data.clone();
         let game_tx = game_tx.clone();
+        let connection = connections_tx.clone();
 
         let _ = task::spawn(async move {
+            let _connection = connection;
             if let Err(err) = handle_client(game_tx, stream, server_data).await {
                 if let McError::PleaseDisconnect = err {
                     debug!("politely closing connection"); // not an error
@@ -112,18 +190,50 @@ async fn accept_clients(host: &str, port: u16, server_data: Arc<ServerData>) ->
                 }
             }
         });
+    };
+
+    // stop accepting new connections
+    drop(incoming);
+    drop(listener);
+
+    // let kicked players' connections flush and close
+    drop(connections_tx);
+    if future::timeout(SHUTDOWN_TIMEOUT, connections_rx.next())
+        .await
+        .is_err()
+    {
+        warn!("timed out waiting for connections to close");
     }
 
-    Ok(())
+    result
 }
 
+const CONFIG_PATH: &str = "server.properties";
+
 pub fn main() {
+    let config = match ServerConfig::load_or_create(CONFIG_PATH) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("failed to load config from {}: {}", CONFIG_PATH, e);
+            std::process::exit(1)
+        }
+    };
+
     // TODO start server thread
-    let server_data = Arc::new(ServerData::new().unwrap());
+    let (host, port) = (config.bind_host().to_owned(), config.port);
+    let server_data = match ServerData::new(config) {
+        Ok(data) => Arc::new(data),
+        Err(e) => {
+            error!("failed to load server data: {}", e);
+            std::process::exit(1)
+        }
+    };
 
-    let accept_future = accept_clients("127.0.0.1", 25565, server_data);
+    let accept_future = accept_clients(&host, port, server_data);
     if let Err(e) = task::block_on(accept_future) {
         error!("failed to run accept loop: {}", e);
         std::process::exit(1)
     }
+
+    info!("server stopped");
 }